@@ -0,0 +1,97 @@
+use crate::run::WASI_NAMESPACES;
+use wasmer::{AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports, Memory, WasmPtr};
+use wasmer_wasix::wasmer_wasix_types::wasi::Errno;
+
+/// The name of the program, its only argument.
+pub const PROGRAM_NAME: &str = "app";
+
+struct ArgsEnv {
+    memory: Option<Memory>,
+}
+
+/// A fixed `args_get` and `environ_get` for a single run, so every program sees the same
+/// arguments and environment on every host: the single argument `PROGRAM_NAME` and no
+/// environment variables.
+pub struct DeterministicArgs {
+    env: FunctionEnv<ArgsEnv>,
+}
+
+impl DeterministicArgs {
+    /// Replace the `args_sizes_get`, `args_get`, `environ_sizes_get` and `environ_get` of the
+    /// WASI imports with ones reporting the fixed arguments and the empty environment.
+    pub fn new(store: &mut impl AsStoreMut, imports: &mut Imports) -> Self {
+        let env = FunctionEnv::new(store, ArgsEnv { memory: None });
+
+        for namespace in WASI_NAMESPACES {
+            let args_sizes_get = Function::new_typed_with_env(store, &env, args_sizes_get);
+            imports.define(namespace, "args_sizes_get", args_sizes_get);
+            let args_get = Function::new_typed_with_env(store, &env, args_get);
+            imports.define(namespace, "args_get", args_get);
+            let environ_sizes_get = Function::new_typed_with_env(store, &env, environ_sizes_get);
+            imports.define(namespace, "environ_sizes_get", environ_sizes_get);
+            let environ_get = Function::new_typed(store, environ_get);
+            imports.define(namespace, "environ_get", environ_get);
+        }
+
+        DeterministicArgs { env }
+    }
+
+    /// Give the functions the memory of the instance to write the arguments to.
+    pub fn initialize(&self, store: &mut impl AsStoreMut, memory: Memory) {
+        self.env.as_mut(store).memory = Some(memory);
+    }
+}
+
+/// Write the bytes at their offsets in the memory of the instance.
+fn write_all(env: &FunctionEnvMut<ArgsEnv>, writes: &[(u32, &[u8])]) -> i32 {
+    let memory = match &env.data().memory {
+        Some(memory) => memory,
+        None => return Errno::Fault as i32,
+    };
+
+    let view = memory.view(env);
+    for &(offset, bytes) in writes {
+        if view.write(offset as u64, bytes).is_err() {
+            return Errno::Fault as i32;
+        }
+    }
+    Errno::Success as i32
+}
+
+fn args_sizes_get(env: FunctionEnvMut<ArgsEnv>, argc: WasmPtr<u32>, size: WasmPtr<u32>) -> i32 {
+    // The name is written with its terminating NUL byte
+    let len = PROGRAM_NAME.len() as u32 + 1;
+    write_all(
+        &env,
+        &[
+            (argc.offset(), &1u32.to_le_bytes()[..]),
+            (size.offset(), &len.to_le_bytes()[..]),
+        ],
+    )
+}
+
+fn args_get(env: FunctionEnvMut<ArgsEnv>, argv: WasmPtr<u32>, buf: WasmPtr<u8>) -> i32 {
+    let name = [PROGRAM_NAME.as_bytes(), &[0]].concat();
+    write_all(
+        &env,
+        &[
+            (argv.offset(), &buf.offset().to_le_bytes()[..]),
+            (buf.offset(), &name[..]),
+        ],
+    )
+}
+
+fn environ_sizes_get(env: FunctionEnvMut<ArgsEnv>, count: WasmPtr<u32>, size: WasmPtr<u32>) -> i32 {
+    write_all(
+        &env,
+        &[
+            (count.offset(), &0u32.to_le_bytes()[..]),
+            (size.offset(), &0u32.to_le_bytes()[..]),
+        ],
+    )
+}
+
+/// There are no variables, so there is nothing to write.
+fn environ_get(_environ: WasmPtr<u32>, _buf: WasmPtr<u8>) -> i32 {
+    Errno::Success as i32
+}
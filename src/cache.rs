@@ -0,0 +1,93 @@
+use crate::config::compile_cache_size;
+use crate::run::{CompileOptions, CompiledModule, RunError};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+
+/// What a compiled module depends on, the module itself, the limits baked into it and the
+/// options it was compiled with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CacheKey {
+    hash: Vec<u8>,
+    budget: u64,
+    mem: u32,
+    options: CompileOptions,
+}
+
+/// A least recently used cache of compiled modules, keyed by the SHA-256 of the module and the
+/// limits and options it was compiled with, so judging many inputs against a module only
+/// compiles it once.
+pub struct ModuleCache {
+    capacity: usize,
+    /// The entries, the most recently used first.
+    entries: Mutex<VecDeque<(CacheKey, CompiledModule)>>,
+}
+
+impl ModuleCache {
+    /// A cache keeping at most `capacity` modules, which caches nothing if it is 0.
+    pub fn new(capacity: usize) -> Self {
+        ModuleCache {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// The compiled module for the given module, limits and options, compiled with `compile` on
+    /// a miss.
+    /// The lock is not held while compiling, so concurrent misses on one module may compile it
+    /// more than once, but never block the runs of other modules.
+    pub fn get_or_compile(
+        &self,
+        wasm: &[u8],
+        budget: u64,
+        mem: u32,
+        options: CompileOptions,
+        compile: impl FnOnce() -> Result<CompiledModule, RunError>,
+    ) -> Result<CompiledModule, RunError> {
+        if self.capacity == 0 {
+            return compile();
+        }
+
+        let key = CacheKey {
+            hash: Sha256::digest(wasm).to_vec(),
+            budget,
+            mem,
+            options,
+        };
+
+        {
+            let mut entries = self.entries.lock().unwrap();
+            if let Some(index) = entries.iter().position(|(k, _)| *k == key) {
+                let entry = entries.remove(index).unwrap();
+                let compiled = entry.1.clone();
+                entries.push_front(entry);
+                return Ok(compiled);
+            }
+        }
+
+        // Compile errors are not cached, they are cheap to reproduce
+        let compiled = compile()?;
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.iter().any(|(k, _)| *k == key) {
+            entries.push_front((key, compiled.clone()));
+            entries.truncate(self.capacity);
+        }
+        Ok(compiled)
+    }
+
+    /// The number of modules in the cache.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The cache shared by every compile, with the capacity set by `COMPILE_CACHE_SIZE`.
+pub fn module_cache() -> &'static ModuleCache {
+    static CACHE: OnceLock<ModuleCache> = OnceLock::new();
+    CACHE.get_or_init(|| ModuleCache::new(compile_cache_size()))
+}
@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use clap::{arg, value_parser, Command};
+use clap_complete::Shell;
 
 pub fn cli() -> Command {
     Command::new("wark")
@@ -22,15 +23,115 @@ pub fn cli() -> Command {
                     arg!(-c --cost <cost> "computational cost limit in instruction count")
                         .default_value("1000000000")
                         .value_parser(value_parser!(u64).range(1..)),
+                    arg!(--compiler <compiler> "compiler backend, singlepass compiles faster but runs slower")
+                        .default_value("cranelift")
+                        .value_parser(["cranelift", "singlepass"]),
+                    arg!(--strict "reject modules using SIMD or threads")
+                        .value_parser(value_parser!(bool)),
                     arg!(-i --input <input> "input file path to the program")
                         .default_value("")
                         .value_parser(value_parser!(String)),
+                    arg!(--stdout <file> "write program's stdout to a file instead")
+                        .value_parser(value_parser!(PathBuf)),
                     arg!(--stderr <file> "redirect program's stderr to a file")
                         .value_parser(value_parser!(PathBuf)),
+                    arg!(--seed <seed> "seed the program's random bytes to make them reproducible")
+                        .value_parser(value_parser!(u64)),
+                    arg!(--"clock-start" <ns> "make the program's clocks deterministic, starting at this time in nanoseconds")
+                        .value_parser(value_parser!(u64)),
+                    arg!(--"clock-increment" <ns> "make the program's clocks deterministic, advancing by this time in nanoseconds on each read")
+                        .value_parser(value_parser!(u64)),
                     arg!(-n --"no-report" "do not report the program's resource usage")
                         .value_parser(value_parser!(bool)),
-                    arg!(<module> "a path to WebAssembly module (.wasm)")
+                    arg!(--json "print a single JSON report with the base64-encoded output to stdout")
+                        .value_parser(value_parser!(bool)),
+                    arg!(--operations "print the operation counts of the run to stderr")
+                        .value_parser(value_parser!(bool)),
+                    arg!(--precompiled "run a module precompiled with compile-to, with the limits it was compiled with")
+                        .value_parser(value_parser!(bool)),
+                    arg!(--"module-auth" <token> "bearer token to fetch the module URL with")
+                        .value_parser(value_parser!(String)),
+                    arg!(<module> "a path or http(s) URL to WebAssembly module (.wasm or .wat), or - to read it from stdin")
+                        .value_parser(value_parser!(PathBuf)),
+                ]),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Compile a WebAssembly module with limitations without running it")
+                .args(&[
+                    arg!(-m --memory <memory> "memory limit in MB")
+                        .default_value("512")
+                        .value_parser(value_parser!(u32).range(1..)),
+                    arg!(-c --cost <cost> "computational cost limit in instruction count")
+                        .default_value("1000000000")
+                        .value_parser(value_parser!(u64).range(1..)),
+                    arg!(--compiler <compiler> "compiler backend, singlepass compiles faster but runs slower")
+                        .default_value("cranelift")
+                        .value_parser(["cranelift", "singlepass"]),
+                    arg!(--strict "reject modules using SIMD or threads")
+                        .value_parser(value_parser!(bool)),
+                    arg!(--"module-auth" <token> "bearer token to fetch the module URL with")
+                        .value_parser(value_parser!(String)),
+                    arg!(<module> "a path or http(s) URL to WebAssembly module (.wasm or .wat), or - to read it from stdin")
+                        .value_parser(value_parser!(PathBuf)),
+                ]),
+        )
+        .subcommand(
+            Command::new("compile-to")
+                .about("Precompile a WebAssembly module with limitations to a file, to run it with run --precompiled")
+                .args(&[
+                    arg!(-m --memory <memory> "memory limit in MB")
+                        .default_value("512")
+                        .value_parser(value_parser!(u32).range(1..)),
+                    arg!(-c --cost <cost> "computational cost limit in instruction count")
+                        .default_value("1000000000")
+                        .value_parser(value_parser!(u64).range(1..)),
+                    arg!(--compiler <compiler> "compiler backend, singlepass compiles faster but runs slower")
+                        .default_value("cranelift")
+                        .value_parser(["cranelift", "singlepass"]),
+                    arg!(--strict "reject modules using SIMD or threads")
+                        .value_parser(value_parser!(bool)),
+                    arg!(--"module-auth" <token> "bearer token to fetch the module URL with")
+                        .value_parser(value_parser!(String)),
+                    arg!(<module> "a path or http(s) URL to WebAssembly module (.wasm or .wat), or - to read it from stdin")
+                        .value_parser(value_parser!(PathBuf)),
+                    arg!(<out> "a path to write the precompiled module to")
                         .value_parser(value_parser!(PathBuf)),
                 ]),
         )
+        .subcommand(
+            Command::new("judge")
+                .about("Judge a WebAssembly module against a judge spec")
+                .args(&[
+                    arg!(--"module-auth" <token> "bearer token to fetch the module URL with")
+                        .value_parser(value_parser!(String)),
+                    arg!(<module> "a path or http(s) URL to WebAssembly module (.wasm or .wat), or - to read it from stdin")
+                        .value_parser(value_parser!(PathBuf)),
+                    arg!(<spec> "a path to the judge spec (.json, .yaml or .yml)")
+                        .value_parser(value_parser!(PathBuf)),
+                ]),
+        )
+        .subcommand(
+            Command::new("analyze")
+                .about("Print the static operation counts of a WebAssembly module")
+                .args(&[
+                    arg!(--"module-auth" <token> "bearer token to fetch the module URL with")
+                        .value_parser(value_parser!(String)),
+                    arg!(<module> "a path or http(s) URL to WebAssembly module (.wasm or .wat), or - to read it from stdin")
+                        .value_parser(value_parser!(PathBuf)),
+                ]),
+        )
+        .subcommand(
+            Command::new("costs")
+                .about("Print the cost of each WebAssembly operator")
+                .args(&[arg!(--json "print the costs as a JSON object")
+                    .value_parser(value_parser!(bool))]),
+        )
+        .subcommand(
+            Command::new("completions")
+                .about("Print the shell completion script of the CLI")
+                .hide(true)
+                .args(&[arg!(<shell> "the shell to complete in")
+                    .value_parser(value_parser!(Shell))]),
+        )
 }
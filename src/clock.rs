@@ -0,0 +1,263 @@
+use crate::run::WASI_NAMESPACES;
+use wasmer::{AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports, Memory, WasmPtr};
+use wasmer_wasix::wasmer_wasix_types::wasi::Errno;
+
+/// The default time of the first `clock_time_get` of a deterministic clock, in nanoseconds.
+pub const DEFAULT_CLOCK_START: u64 = 0;
+
+/// The default time a deterministic clock advances by on each `clock_time_get`, in nanoseconds.
+pub const DEFAULT_CLOCK_INCREMENT: u64 = 1_000_000;
+
+/// What the clocks of a run read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClockMode {
+    /// The real clocks of the host, so runs are not reproducible.
+    #[default]
+    System,
+    /// A counter starting at `start` nanoseconds and advancing by `increment` nanoseconds on
+    /// each read, the same for every clock, so runs always see the same times.
+    Deterministic { start: u64, increment: u64 },
+}
+
+struct ClockEnv {
+    memory: Option<Memory>,
+    /// The counter read by the clocks, if they are deterministic.
+    counter: Option<Counter>,
+}
+
+struct Counter {
+    now: u64,
+    increment: u64,
+}
+
+/// The size of a WASI `subscription`, and the offsets of the clock fields read by `poll_oneoff`.
+struct SubscriptionLayout {
+    size: u64,
+    timeout: usize,
+    flags: usize,
+}
+
+/// The `subscription` of `wasi_snapshot_preview1` and later.
+const SUBSCRIPTION: SubscriptionLayout = SubscriptionLayout {
+    size: 48,
+    timeout: 24,
+    flags: 40,
+};
+
+/// The `subscription` of `wasi_unstable`, whose clock subscriptions start with an identifier.
+const SUBSCRIPTION_PREVIEW0: SubscriptionLayout = SubscriptionLayout {
+    size: 56,
+    timeout: 32,
+    flags: 48,
+};
+
+/// The offset of the tag of a subscription, the same in every WASI version.
+const SUBSCRIPTION_TAG: usize = 8;
+
+/// The tag of a clock subscription, and the flag making its timeout an absolute time.
+const EVENTTYPE_CLOCK: u8 = 0;
+const SUBCLOCKFLAGS_ABSTIME: u16 = 1;
+
+/// The size of a WASI `event`, and the offsets of its fields.
+const EVENT_SIZE: usize = 32;
+const EVENT_ERROR: usize = 8;
+const EVENT_TYPE: usize = 10;
+
+/// The clock and scheduling functions of a single run.
+/// `sched_yield` and `poll_oneoff` are always stubbed to return right away, so a program cannot
+/// stall on the scheduler, or on timers and file descriptors that never fire. With a
+/// deterministic mode, `clock_time_get` and `clock_res_get` read a counter instead of the host
+/// clocks, so time moves forward by the same steps on every run, and a program waiting for some
+/// time to pass does not hang.
+pub struct Clock {
+    env: FunctionEnv<ClockEnv>,
+}
+
+impl Clock {
+    /// Stub out the `sched_yield` and `poll_oneoff` of the WASI imports. With a deterministic
+    /// mode, also replace the `clock_time_get` with one reading the counter, and the
+    /// `clock_res_get` with one reporting its increment as the resolution of every clock.
+    pub fn new(store: &mut impl AsStoreMut, imports: &mut Imports, mode: ClockMode) -> Self {
+        let counter = match mode {
+            ClockMode::Deterministic { start, increment } => Some(Counter {
+                now: start,
+                increment,
+            }),
+            ClockMode::System => None,
+        };
+        let deterministic = counter.is_some();
+        let env = FunctionEnv::new(
+            store,
+            ClockEnv {
+                memory: None,
+                counter,
+            },
+        );
+
+        for namespace in WASI_NAMESPACES {
+            if deterministic {
+                let clock_time_get = Function::new_typed_with_env(store, &env, clock_time_get);
+                imports.define(namespace, "clock_time_get", clock_time_get);
+                let clock_res_get = Function::new_typed_with_env(store, &env, clock_res_get);
+                imports.define(namespace, "clock_res_get", clock_res_get);
+            }
+            let sched_yield = Function::new_typed(store, sched_yield);
+            imports.define(namespace, "sched_yield", sched_yield);
+            let poll_oneoff = if namespace == "wasi_unstable" {
+                Function::new_typed_with_env(store, &env, poll_oneoff_preview0)
+            } else {
+                Function::new_typed_with_env(store, &env, poll_oneoff)
+            };
+            imports.define(namespace, "poll_oneoff", poll_oneoff);
+        }
+
+        Clock { env }
+    }
+
+    /// Give the functions the memory of the instance to write the times to.
+    pub fn initialize(&self, store: &mut impl AsStoreMut, memory: Memory) {
+        self.env.as_mut(store).memory = Some(memory);
+    }
+}
+
+fn clock_time_get(
+    mut env: FunctionEnvMut<ClockEnv>,
+    _clock_id: u32,
+    _precision: u64,
+    time: WasmPtr<u64>,
+) -> i32 {
+    let (data, store) = env.data_and_store_mut();
+    let (memory, counter) = match (&data.memory, &mut data.counter) {
+        (Some(memory), Some(counter)) => (memory, counter),
+        _ => return Errno::Fault as i32,
+    };
+
+    let now = counter.now;
+    counter.now = counter.now.saturating_add(counter.increment);
+
+    match memory
+        .view(&store)
+        .write(time.offset() as u64, &now.to_le_bytes())
+    {
+        Ok(_) => Errno::Success as i32,
+        Err(_) => Errno::Fault as i32,
+    }
+}
+
+fn clock_res_get(env: FunctionEnvMut<ClockEnv>, _clock_id: u32, resolution: WasmPtr<u64>) -> i32 {
+    let data = env.data();
+    let (memory, counter) = match (&data.memory, &data.counter) {
+        (Some(memory), Some(counter)) => (memory, counter),
+        _ => return Errno::Fault as i32,
+    };
+
+    // The clock never moves by less than its increment, but a resolution of zero is not valid
+    let value = counter.increment.max(1);
+
+    match memory
+        .view(&env)
+        .write(resolution.offset() as u64, &value.to_le_bytes())
+    {
+        Ok(_) => Errno::Success as i32,
+        Err(_) => Errno::Fault as i32,
+    }
+}
+
+fn sched_yield() -> i32 {
+    Errno::Success as i32
+}
+
+fn poll_oneoff(
+    env: FunctionEnvMut<ClockEnv>,
+    subscriptions: WasmPtr<u8>,
+    events: WasmPtr<u8>,
+    count: u32,
+    nevents: WasmPtr<u32>,
+) -> i32 {
+    poll_subscriptions(&SUBSCRIPTION, env, subscriptions, events, count, nevents)
+}
+
+fn poll_oneoff_preview0(
+    env: FunctionEnvMut<ClockEnv>,
+    subscriptions: WasmPtr<u8>,
+    events: WasmPtr<u8>,
+    count: u32,
+    nevents: WasmPtr<u32>,
+) -> i32 {
+    poll_subscriptions(
+        &SUBSCRIPTION_PREVIEW0,
+        env,
+        subscriptions,
+        events,
+        count,
+        nevents,
+    )
+}
+
+/// Report every subscription, laid out as given, as ready right away. A clock subscription moves
+/// a deterministic clock to the end of its timeout instead of waiting for it, and a file
+/// descriptor subscription is ready with no bytes, so the program goes on to read or write it
+/// directly. Events are laid out the same in every WASI version.
+fn poll_subscriptions(
+    layout: &SubscriptionLayout,
+    mut env: FunctionEnvMut<ClockEnv>,
+    subscriptions: WasmPtr<u8>,
+    events: WasmPtr<u8>,
+    count: u32,
+    nevents: WasmPtr<u32>,
+) -> i32 {
+    let (data, store) = env.data_and_store_mut();
+    let memory = match &data.memory {
+        Some(memory) => memory,
+        None => return Errno::Fault as i32,
+    };
+    let view = memory.view(&store);
+
+    // Check the size before allocating, the count comes from the program
+    let size = count as u64 * layout.size;
+    if subscriptions.offset() as u64 + size > view.data_size() {
+        return Errno::Fault as i32;
+    }
+    let mut input = vec![0; size as usize];
+    if view
+        .read(subscriptions.offset() as u64, &mut input)
+        .is_err()
+    {
+        return Errno::Fault as i32;
+    }
+
+    let mut output = vec![0; count as usize * EVENT_SIZE];
+    for (subscription, event) in input
+        .chunks_exact(layout.size as usize)
+        .zip(output.chunks_exact_mut(EVENT_SIZE))
+    {
+        let tag = subscription[SUBSCRIPTION_TAG];
+        if let (EVENTTYPE_CLOCK, Some(counter)) = (tag, &mut data.counter) {
+            let timeout = read_u64(subscription, layout.timeout);
+            let flags =
+                u16::from_le_bytes([subscription[layout.flags], subscription[layout.flags + 1]]);
+            counter.now = if flags & SUBCLOCKFLAGS_ABSTIME != 0 {
+                counter.now.max(timeout)
+            } else {
+                counter.now.saturating_add(timeout)
+            };
+        }
+
+        // Echo the userdata, with no error and the type of the subscription
+        event[..8].copy_from_slice(&subscription[..8]);
+        event[EVENT_ERROR..EVENT_ERROR + 2].copy_from_slice(&(Errno::Success as u16).to_le_bytes());
+        event[EVENT_TYPE] = tag;
+    }
+
+    let written = view
+        .write(events.offset() as u64, &output)
+        .and_then(|_| view.write(nevents.offset() as u64, &count.to_le_bytes()));
+    match written {
+        Ok(_) => Errno::Success as i32,
+        Err(_) => Errno::Fault as i32,
+    }
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
@@ -1,4 +1,64 @@
+use crate::run::{CompileOptions, Compiler};
 use std::env;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::OnceLock;
+use std::thread;
+
+/// The configuration read on every request or run, parsed from the environment once by `config`.
+/// The free functions below read the environment directly on every call, so they are only used
+/// to build the configuration, or for settings read once at startup.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub max_cost: u64,
+    pub max_memory: u32,
+    pub compiler: Compiler,
+    pub canonicalize_nans: bool,
+    pub strict: bool,
+    pub allowed_imports: Vec<String>,
+    pub max_batch_size: usize,
+    pub max_queued_jobs: usize,
+    pub job_ttl: u64,
+    pub helper_time_limit: u64,
+    pub server_port: u16,
+    pub app_secret: String,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            max_cost: max_cost(),
+            max_memory: max_memory(),
+            compiler: compiler(),
+            canonicalize_nans: canonicalize_nans(),
+            strict: strict(),
+            allowed_imports: allowed_imports(),
+            max_batch_size: max_batch_size(),
+            max_queued_jobs: max_queued_jobs(),
+            job_ttl: job_ttl(),
+            helper_time_limit: helper_time_limit(),
+            server_port: server_port(),
+            app_secret: app_secret(),
+        }
+    }
+}
+
+impl Config {
+    /// The options metered submissions are compiled with.
+    pub fn compile_options(&self) -> CompileOptions {
+        CompileOptions {
+            metered: true,
+            compiler: self.compiler,
+            canonicalize_nans: self.canonicalize_nans,
+            strict: self.strict,
+        }
+    }
+}
+
+/// The configuration, parsed from the environment on first use.
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(Config::from_env)
+}
 
 /// Fetches the maximum computational cost limit from the environment variable "MAX_COST".
 /// If the variable is not set or its value cannot be parsed into u64, a default value of 1,000,000,000 is returned.
@@ -18,6 +78,47 @@ pub fn max_memory() -> u32 {
         .unwrap_or(4096)
 }
 
+/// Fetches the compiler backend of the submissions from the environment variable "COMPILER", either "cranelift" or "singlepass".
+/// If the variable is not set or its value is not a known compiler, Cranelift is returned.
+pub fn compiler() -> Compiler {
+    env::var("COMPILER")
+        .ok()
+        .and_then(|val| val.parse::<Compiler>().ok())
+        .unwrap_or_default()
+}
+
+/// Fetches whether to canonicalize the NaNs of floating-point operations from the environment variable "CANONICALIZE_NANS".
+/// If the variable is not set or its value cannot be parsed into bool, a default value of true is returned.
+pub fn canonicalize_nans() -> bool {
+    env::var("CANONICALIZE_NANS")
+        .unwrap_or("true".to_owned())
+        .parse::<bool>()
+        .unwrap_or(true)
+}
+
+/// Fetches whether to reject modules using SIMD or threads from the environment variable "STRICT".
+/// If the variable is not set or its value cannot be parsed into bool, a default value of false is returned.
+pub fn strict() -> bool {
+    env::var("STRICT")
+        .unwrap_or("false".to_owned())
+        .parse::<bool>()
+        .unwrap_or(false)
+}
+
+/// The import namespaces allowed when the "ALLOWED_IMPORTS" environment variable is not set, the WASI namespaces.
+pub const DEFAULT_ALLOWED_IMPORTS: &str = "wasi_unstable,wasi_snapshot_preview1,wasix_32v1";
+
+/// Fetches the namespaces modules may import from from the environment variable "ALLOWED_IMPORTS", separated by commas.
+/// If the variable is not set, the WASI namespaces in DEFAULT_ALLOWED_IMPORTS are returned.
+pub fn allowed_imports() -> Vec<String> {
+    env::var("ALLOWED_IMPORTS")
+        .unwrap_or(DEFAULT_ALLOWED_IMPORTS.to_owned())
+        .split(',')
+        .map(|namespace| namespace.trim().to_owned())
+        .filter(|namespace| !namespace.is_empty())
+        .collect()
+}
+
 /// Fetches the server port number from the environment variable "PORT".
 /// If the variable is not set or its value cannot be parsed into u16, a default value of 33000 is returned.
 pub fn server_port() -> u16 {
@@ -27,8 +128,211 @@ pub fn server_port() -> u16 {
         .unwrap_or(33000)
 }
 
+/// Fetches the address the server binds to from the environment variable "BIND_ADDRESS", either IPv4 or IPv6.
+/// If the variable is not set or its value cannot be parsed into an IpAddr, a default value of 0.0.0.0 is returned.
+pub fn bind_address() -> IpAddr {
+    env::var("BIND_ADDRESS")
+        .unwrap_or("0.0.0.0".to_owned())
+        .parse::<IpAddr>()
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+}
+
+/// The application secret used when the "APP_SECRET" environment variable is not set.
+pub const DEFAULT_APP_SECRET: &str = "APP_SECRET";
+
 /// Fetches the application secret from the environment variable "APP_SECRET".
 /// If the variable is not set, a default value of "APP_SECRET" is returned.
 pub fn app_secret() -> String {
-    env::var("APP_SECRET").unwrap_or("APP_SECRET".to_owned())
+    env::var("APP_SECRET").unwrap_or(DEFAULT_APP_SECRET.to_owned())
+}
+
+/// Fetches whether the server may start with the default application secret in release builds
+/// from the environment variable "ALLOW_INSECURE_SECRET", which is false unless set to "1" or "true".
+pub fn allow_insecure_secret() -> bool {
+    matches!(
+        env::var("ALLOW_INSECURE_SECRET").as_deref(),
+        Ok("1") | Ok("true")
+    )
+}
+
+/// Fetches the maximum number of unfinished jobs from the environment variable "MAX_QUEUED_JOBS".
+/// If the variable is not set or its value cannot be parsed into usize, a default value of 64 is returned.
+pub fn max_queued_jobs() -> usize {
+    env::var("MAX_QUEUED_JOBS")
+        .unwrap_or("64".to_owned())
+        .parse::<usize>()
+        .unwrap_or(64)
+}
+
+/// Fetches how long finished jobs are kept, in seconds, from the environment variable "JOB_TTL".
+/// If the variable is not set or its value cannot be parsed into u64, a default value of 3600 is returned.
+pub fn job_ttl() -> u64 {
+    env::var("JOB_TTL")
+        .unwrap_or("3600".to_owned())
+        .parse::<u64>()
+        .unwrap_or(3600)
+}
+
+/// Fetches the wall-clock time limit of the helper modules of judge specs, such as checkers and generators, in milliseconds, from the environment variable "HELPER_TIME_LIMIT".
+/// If the variable is not set or its value cannot be parsed into a positive u64, a default value of 10000 is returned.
+pub fn helper_time_limit() -> u64 {
+    env::var("HELPER_TIME_LIMIT")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(10000)
+}
+
+/// Fetches the maximum number of modules run at the same time from the environment variable "MAX_CONCURRENT_RUNS".
+/// If the variable is not set or its value cannot be parsed into a positive usize, the number of available CPUs is returned.
+pub fn max_concurrent_runs() -> usize {
+    env::var("MAX_CONCURRENT_RUNS")
+        .ok()
+        .and_then(|val| val.parse::<usize>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()))
+}
+
+/// Fetches the maximum number of compiled modules kept in memory from the environment variable "COMPILE_CACHE_SIZE".
+/// A value of 0 disables the cache. If the variable is not set or its value cannot be parsed into usize, a default value of 32 is returned.
+pub fn compile_cache_size() -> usize {
+    env::var("COMPILE_CACHE_SIZE")
+        .unwrap_or("32".to_owned())
+        .parse::<usize>()
+        .unwrap_or(32)
+}
+
+/// Fetches the maximum number of specs of a single submission judged at the same time from the environment variable "MAX_PARALLEL_SPECS".
+/// If the variable is not set or its value cannot be parsed into a positive usize, a default value of 4 is returned.
+pub fn max_parallel_specs() -> usize {
+    env::var("MAX_PARALLEL_SPECS")
+        .ok()
+        .and_then(|val| val.parse::<usize>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(4)
+}
+
+/// Fetches the maximum number of submissions of a single `/run/batch` request from the environment variable "MAX_BATCH_SIZE".
+/// If the variable is not set or its value cannot be parsed into a positive usize, a default value of 64 is returned.
+pub fn max_batch_size() -> usize {
+    env::var("MAX_BATCH_SIZE")
+        .ok()
+        .and_then(|val| val.parse::<usize>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(64)
+}
+
+/// Fetches the maximum size of a remote input or output of a judge spec, in bytes, from the environment variable "MAX_INPUT_SIZE".
+/// The limit applies both to the fetched body and to its decompressed content.
+/// If the variable is not set or its value cannot be parsed into u64, a default value of 268435456 (256 MiB) is returned.
+pub fn max_input_size() -> u64 {
+    env::var("MAX_INPUT_SIZE")
+        .unwrap_or("268435456".to_owned())
+        .parse::<u64>()
+        .unwrap_or(268435456)
+}
+
+/// Fetches the timeout of fetching a remote file, in seconds, from the environment variable "FETCH_TIMEOUT".
+/// If the variable is not set or its value cannot be parsed into a positive u64, a default value of 30 is returned.
+pub fn fetch_timeout() -> u64 {
+    env::var("FETCH_TIMEOUT")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(30)
+}
+
+/// Fetches the directory the remote files of the judge specs are cached in on disk from the environment variable "INPUT_CACHE_DIR", if any.
+/// If the variable is not set, the default directory of the cache is used.
+pub fn input_cache_dir() -> Option<String> {
+    env::var("INPUT_CACHE_DIR").ok()
+}
+
+/// Fetches the number of attempts to fetch a remote file from the environment variable "FETCH_ATTEMPTS".
+/// If the variable is not set or its value cannot be parsed into a positive u32, a default value of 3 is returned.
+pub fn fetch_attempts() -> u32 {
+    env::var("FETCH_ATTEMPTS")
+        .ok()
+        .and_then(|val| val.parse::<u32>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(3)
+}
+
+/// Fetches the delay before the first fetch retry, in milliseconds, from the environment variable "FETCH_RETRY_DELAY".
+/// If the variable is not set or its value cannot be parsed into u64, a default value of 500 is returned.
+pub fn fetch_retry_delay() -> u64 {
+    env::var("FETCH_RETRY_DELAY")
+        .unwrap_or("500".to_owned())
+        .parse::<u64>()
+        .unwrap_or(500)
+}
+
+/// Fetches the number of attempts to send a callback from the environment variable "CALLBACK_ATTEMPTS".
+/// If the variable is not set or its value cannot be parsed into a positive u32, a default value of 3 is returned.
+pub fn callback_attempts() -> u32 {
+    env::var("CALLBACK_ATTEMPTS")
+        .ok()
+        .and_then(|val| val.parse::<u32>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(3)
+}
+
+/// Fetches the delay before the first callback retry, in milliseconds, from the environment variable "CALLBACK_RETRY_DELAY".
+/// If the variable is not set or its value cannot be parsed into u64, a default value of 1000 is returned.
+pub fn callback_retry_delay() -> u64 {
+    env::var("CALLBACK_RETRY_DELAY")
+        .unwrap_or("1000".to_owned())
+        .parse::<u64>()
+        .unwrap_or(1000)
+}
+
+/// Fetches the timeout of sending a callback, in seconds, from the environment variable "CALLBACK_TIMEOUT".
+/// If the variable is not set or its value cannot be parsed into a positive u64, a default value of 30 is returned.
+pub fn callback_timeout() -> u64 {
+    env::var("CALLBACK_TIMEOUT")
+        .ok()
+        .and_then(|val| val.parse::<u64>().ok())
+        .filter(|&val| val > 0)
+        .unwrap_or(30)
+}
+
+/// Fetches the algorithm of the JWT tokens from the environment variable "JWT_ALGORITHM".
+/// If the variable is not set, a default value of "HS256" is returned, which verifies tokens with the application secret.
+pub fn jwt_algorithm() -> String {
+    env::var("JWT_ALGORITHM").unwrap_or("HS256".to_owned())
+}
+
+/// Fetches the PEM public key verifying RS256 and ES256 tokens from the environment variable "JWT_PUBLIC_KEY".
+/// The variable holds either the PEM content or the path to a PEM file.
+pub fn jwt_public_key() -> Option<String> {
+    env::var("JWT_PUBLIC_KEY").ok()
+}
+
+/// Fetches the audience required in the JWT tokens from the environment variable "JWT_AUDIENCE", if any.
+pub fn jwt_audience() -> Option<String> {
+    env::var("JWT_AUDIENCE").ok()
+}
+
+/// Fetches the issuer required in the JWT tokens from the environment variable "JWT_ISSUER", if any.
+pub fn jwt_issuer() -> Option<String> {
+    env::var("JWT_ISSUER").ok()
+}
+
+/// Fetches how long the server waits for running work on shutdown, in seconds, from the environment variable "DRAIN_TIMEOUT".
+/// If the variable is not set or its value cannot be parsed into u64, a default value of 30 is returned.
+pub fn drain_timeout() -> u64 {
+    env::var("DRAIN_TIMEOUT")
+        .unwrap_or("30".to_owned())
+        .parse::<u64>()
+        .unwrap_or(30)
+}
+
+/// Fetches the path to the PEM certificate chain of the server from the environment variable "TLS_CERT", if any.
+pub fn tls_cert() -> Option<String> {
+    env::var("TLS_CERT").ok()
+}
+
+/// Fetches the path to the PEM private key of the server from the environment variable "TLS_KEY", if any.
+pub fn tls_key() -> Option<String> {
+    env::var("TLS_KEY").ok()
 }
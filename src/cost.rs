@@ -1,16 +1,64 @@
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
 use wasmer::wasmparser::{BlockType as WpTypeOrFuncType, Operator};
 use wasmer::{
-    AsStoreMut, ExportIndex, FunctionMiddleware, GlobalInit, GlobalType, Instance,
+    AsStoreMut, ExportIndex, Extern, FunctionMiddleware, GlobalInit, GlobalType, Instance,
     LocalFunctionIndex, MiddlewareError, MiddlewareReaderState, ModuleMiddleware, Mutability, Type,
+    Value,
 };
+use wasmer_types::entity::EntityRef;
 use wasmer_types::{GlobalIndex, ModuleInfo};
 
+/// The cost of an operator that is not included in the cost table.
+pub const PENALTY_COST: u64 = 1000;
+
+/// The number of bytes touched by a bulk memory operation (`memory.init`, `memory.copy` and
+/// `memory.fill`) that costs one point, on top of the flat cost of the operation itself.
+/// Filling 1 MB costs 131,072 points, which is still far cheaper than an equivalent store loop.
+pub const BULK_MEMORY_BYTES_PER_POINT: u64 = 8;
+
+/// The number of entries touched by a bulk table operation (`table.grow`, `table.init`,
+/// `table.copy` and `table.fill`) that costs one point, on top of the flat cost of the operation.
+pub const BULK_TABLE_ENTRIES_PER_POINT: u64 = 1;
+
+/// The default name prefix of the globals exported by the Cost middleware.
+pub const DEFAULT_EXPORT_PREFIX: &str = "compilet_cost";
+
+/// The export name of the global holding the remaining points.
+fn remaining_points_export(prefix: &str) -> String {
+    format!("{}_remaining_points", prefix)
+}
+
+/// The export name of the global holding whether the points are exhausted.
+fn points_exhausted_export(prefix: &str) -> String {
+    format!("{}_points_exhausted", prefix)
+}
+
+/// The export name of the global counting the executed `memory.grow` operations.
+fn memory_grows_export(prefix: &str) -> String {
+    format!("{}_memory_grows", prefix)
+}
+
+/// The export name of the global counting the `memory.grow` operations that failed.
+fn failed_memory_grows_export(prefix: &str) -> String {
+    format!("{}_failed_memory_grows", prefix)
+}
+
+/// The export name prefix of the globals holding the points spent by each local function.
+fn function_cost_export_prefix(prefix: &str) -> String {
+    format!("{}_function_", prefix)
+}
+
 #[derive(Clone)]
-struct CostGlobalIndexes(GlobalIndex, GlobalIndex);
+struct CostGlobalIndexes(
+    GlobalIndex,
+    GlobalIndex,
+    GlobalIndex,
+    GlobalIndex,
+    GlobalIndex,
+);
 
 impl CostGlobalIndexes {
     /// The global index in the current module for remaining points.
@@ -26,6 +74,24 @@ impl CostGlobalIndexes {
     fn points_exhausted(&self) -> GlobalIndex {
         self.1
     }
+
+    /// The global index in the current module for a scratch i32, holding an operand or a result
+    /// taken off the stack while the injected instructions use it: the length operand of a bulk
+    /// memory operation while it is charged, or the result of a `memory.grow` while it is counted.
+    fn scratch(&self) -> GlobalIndex {
+        self.2
+    }
+
+    /// The global index in the current module for the number of executed `memory.grow` operations.
+    fn memory_grows(&self) -> GlobalIndex {
+        self.3
+    }
+
+    /// The global index in the current module for the number of `memory.grow` operations that
+    /// failed, returning -1.
+    fn failed_memory_grows(&self) -> GlobalIndex {
+        self.4
+    }
 }
 
 impl fmt::Debug for CostGlobalIndexes {
@@ -33,6 +99,9 @@ impl fmt::Debug for CostGlobalIndexes {
         f.debug_struct("CostGlobalIndexes")
             .field("remaining_points", &self.remaining_points())
             .field("points_exhausted", &self.points_exhausted())
+            .field("scratch", &self.scratch())
+            .field("memory_grows", &self.memory_grows())
+            .field("failed_memory_grows", &self.failed_memory_grows())
             .finish()
     }
 }
@@ -41,11 +110,23 @@ pub struct Cost {
     /// Limit of points.
     budget: u64,
 
+    /// The name prefix of the exported globals.
+    prefix: String,
+
     /// The global indexes for Cost points.
     global_indexes: Mutex<Option<CostGlobalIndexes>>,
 
+    /// The global indexes for the points spent by each local function.
+    function_cost_indexes: Mutex<Vec<GlobalIndex>>,
+
     /// Accumulated counts of each operator.
     pub operation_counts: Arc<Mutex<HashMap<String, u64>>>,
+
+    /// Accumulated costs of each operator, the count multiplied by the operator cost.
+    pub operation_costs: Arc<Mutex<HashMap<String, u64>>>,
+
+    /// Names of the local functions, taken from the name section or the exports.
+    pub function_names: Arc<Mutex<HashMap<u32, String>>>,
 }
 
 /// The function-level Cost middleware.
@@ -53,11 +134,17 @@ pub struct FunctionCost {
     /// The global indexes for Cost points.
     global_indexes: CostGlobalIndexes,
 
+    /// The global index for the points spent by the current function.
+    function_cost_index: GlobalIndex,
+
     /// Accumulated cost of the current basic block.
     accumulated_cost: u64,
 
     /// Accumulated counts of each operator.
     operation_counts: Arc<Mutex<HashMap<String, u64>>>,
+
+    /// Accumulated costs of each operator.
+    operation_costs: Arc<Mutex<HashMap<String, u64>>>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -74,20 +161,31 @@ pub enum CostPoints {
 }
 
 impl Cost {
-    /// Creates a `Cost` middleware.
-    pub fn new(budget: u64) -> Self {
+    /// Creates a `Cost` middleware whose globals are exported under the given name prefix.
+    /// Use [`DEFAULT_EXPORT_PREFIX`] unless it collides with an export of the guest module.
+    pub fn new(budget: u64, prefix: &str) -> Self {
         Self {
             budget,
+            prefix: prefix.to_string(),
             global_indexes: Mutex::new(None),
+            function_cost_indexes: Mutex::new(Vec::new()),
             operation_counts: Arc::new(Mutex::new(HashMap::new())),
+            operation_costs: Arc::new(Mutex::new(HashMap::new())),
+            function_names: Arc::new(Mutex::new(HashMap::new())),
         }
     }
+
+    /// The name prefix of the exported globals.
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
 }
 
 impl fmt::Debug for Cost {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Cost")
             .field("budget", &self.budget)
+            .field("prefix", &self.prefix)
             .field("cost_function", &"<function>")
             .field("global_indexes", &self.global_indexes)
             .finish()
@@ -96,11 +194,17 @@ impl fmt::Debug for Cost {
 
 impl ModuleMiddleware for Cost {
     /// Generates a `FunctionMiddleware` for a given function.
-    fn generate_function_middleware(&self, _: LocalFunctionIndex) -> Box<dyn FunctionMiddleware> {
+    fn generate_function_middleware(
+        &self,
+        local_function_index: LocalFunctionIndex,
+    ) -> Box<dyn FunctionMiddleware> {
         Box::new(FunctionCost {
             global_indexes: self.global_indexes.lock().unwrap().clone().unwrap(),
+            function_cost_index: self.function_cost_indexes.lock().unwrap()
+                [local_function_index.index()],
             accumulated_cost: 0,
             operation_counts: self.operation_counts.clone(),
+            operation_costs: self.operation_costs.clone(),
         })
     }
 
@@ -122,7 +226,7 @@ impl ModuleMiddleware for Cost {
             .push(GlobalInit::I64Const(self.budget as i64));
 
         module_info.exports.insert(
-            "compilet_cost_remaining_points".to_string(),
+            remaining_points_export(&self.prefix),
             ExportIndex::Global(remaining_points_global_index),
         );
 
@@ -136,14 +240,100 @@ impl ModuleMiddleware for Cost {
             .push(GlobalInit::I32Const(0));
 
         module_info.exports.insert(
-            "compilet_cost_points_exhausted".to_string(),
+            points_exhausted_export(&self.prefix),
             ExportIndex::Global(points_exhausted_global_index),
         );
 
+        // Append a scratch global for the operands and results used by the injected instructions.
+        let scratch_global_index = module_info
+            .globals
+            .push(GlobalType::new(Type::I32, Mutability::Var));
+
+        module_info
+            .global_initializers
+            .push(GlobalInit::I32Const(0));
+
+        // Append a global counting the executed `memory.grow` operations.
+        let memory_grows_global_index = module_info
+            .globals
+            .push(GlobalType::new(Type::I64, Mutability::Var));
+
+        module_info
+            .global_initializers
+            .push(GlobalInit::I64Const(0));
+
+        module_info.exports.insert(
+            memory_grows_export(&self.prefix),
+            ExportIndex::Global(memory_grows_global_index),
+        );
+
+        // Append a global counting the `memory.grow` operations that failed.
+        let failed_memory_grows_global_index = module_info
+            .globals
+            .push(GlobalType::new(Type::I64, Mutability::Var));
+
+        module_info
+            .global_initializers
+            .push(GlobalInit::I64Const(0));
+
+        module_info.exports.insert(
+            failed_memory_grows_export(&self.prefix),
+            ExportIndex::Global(failed_memory_grows_global_index),
+        );
+
         *global_indexes = Some(CostGlobalIndexes(
             remaining_points_global_index,
             points_exhausted_global_index,
-        ))
+            scratch_global_index,
+            memory_grows_global_index,
+            failed_memory_grows_global_index,
+        ));
+
+        // Append a global for the points spent by each local function and initialize it.
+        let mut function_cost_indexes = self.function_cost_indexes.lock().unwrap();
+        let mut function_names = self.function_names.lock().unwrap();
+        let local_functions = module_info.functions.len() - module_info.num_imported_functions;
+        for local_index in 0..local_functions {
+            let function_index = module_info.func_index(LocalFunctionIndex::new(local_index));
+
+            let function_cost_global_index = module_info
+                .globals
+                .push(GlobalType::new(Type::I64, Mutability::Var));
+
+            module_info
+                .global_initializers
+                .push(GlobalInit::I64Const(0));
+
+            module_info.exports.insert(
+                format!(
+                    "{}{}",
+                    function_cost_export_prefix(&self.prefix),
+                    local_index
+                ),
+                ExportIndex::Global(function_cost_global_index),
+            );
+
+            function_cost_indexes.push(function_cost_global_index);
+
+            let name = module_info
+                .function_names
+                .get(&function_index)
+                .cloned()
+                .or_else(|| {
+                    module_info
+                        .exports
+                        .iter()
+                        .find_map(|(name, export)| match export {
+                            ExportIndex::Function(index) if *index == function_index => {
+                                Some(name.clone())
+                            }
+                            _ => None,
+                        })
+                });
+            if let Some(name) = name {
+                function_names.insert(local_index as u32, name);
+            }
+        }
     }
 }
 
@@ -156,6 +346,57 @@ impl fmt::Debug for FunctionCost {
     }
 }
 
+impl FunctionCost {
+    /// Emit the check and the subtraction of the cost accumulated so far, and reset the accumulator.
+    fn flush_accumulated_cost(&mut self, state: &mut MiddlewareReaderState<'_>) {
+        if self.accumulated_cost > 0 {
+            state.extend(&[
+                // if unsigned(globals[remaining_points_index]) < unsigned(self.accumulated_cost) { throw(); }
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::I64Const {
+                    value: self.accumulated_cost as i64,
+                },
+                Operator::I64LtU,
+                Operator::If {
+                    blockty: WpTypeOrFuncType::Empty,
+                },
+                Operator::I32Const { value: 1 },
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.points_exhausted().as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+                // globals[remaining_points_index] -= self.accumulated_cost;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::I64Const {
+                    value: self.accumulated_cost as i64,
+                },
+                Operator::I64Sub,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                // globals[function_cost_index] += self.accumulated_cost;
+                Operator::GlobalGet {
+                    global_index: self.function_cost_index.as_u32(),
+                },
+                Operator::I64Const {
+                    value: self.accumulated_cost as i64,
+                },
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: self.function_cost_index.as_u32(),
+                },
+            ]);
+
+            self.accumulated_cost = 0;
+        }
+    }
+}
+
 impl FunctionMiddleware for FunctionCost {
     fn feed<'a>(
         &mut self,
@@ -165,287 +406,35 @@ impl FunctionMiddleware for FunctionCost {
         // Get the cost of the current operator, and add it to the accumulator.
         // This needs to be done before the Cost logic, to prevent operators like `Call` from escaping Cost in some
         // corner cases.
-        // Reference: https://nemequ.github.io/waspr/instructions
-        // Reference: https://github.com/WebAssembly/binaryen/blob/main/src/ir/cost.h
-        self.accumulated_cost += match operator {
-            Operator::LocalGet { .. } => 0,
-            Operator::LocalSet { .. } | Operator::LocalTee { .. } => 1,
-            Operator::GlobalGet { .. } => 1,
-            Operator::GlobalSet { .. } => 2,
-            Operator::F32Load { .. }
-            | Operator::F64Load { .. }
-            | Operator::I32Load { .. }
-            | Operator::I64Load { .. }
-            | Operator::I32Load8S { .. }
-            | Operator::I32Load8U { .. }
-            | Operator::I32Load16S { .. }
-            | Operator::I32Load16U { .. }
-            | Operator::I64Load8S { .. }
-            | Operator::I64Load8U { .. }
-            | Operator::I64Load16S { .. }
-            | Operator::I64Load16U { .. }
-            | Operator::I64Load32S { .. }
-            | Operator::I64Load32U { .. } => 1,
-            Operator::I32AtomicLoad { .. }
-            | Operator::I32AtomicLoad8U { .. }
-            | Operator::I32AtomicLoad16U { .. }
-            | Operator::I64AtomicLoad { .. }
-            | Operator::I64AtomicLoad8U { .. }
-            | Operator::I64AtomicLoad16U { .. }
-            | Operator::I64AtomicLoad32U { .. } => 10 + 1,
-            Operator::F32Store { .. }
-            | Operator::F64Store { .. }
-            | Operator::I32Store { .. }
-            | Operator::I64Store { .. }
-            | Operator::I32Store8 { .. }
-            | Operator::I32Store16 { .. }
-            | Operator::I64Store8 { .. }
-            | Operator::I64Store16 { .. }
-            | Operator::I64Store32 { .. } => 2,
-            Operator::I32AtomicStore { .. }
-            | Operator::I32AtomicStore8 { .. }
-            | Operator::I32AtomicStore16 { .. }
-            | Operator::I64AtomicStore { .. }
-            | Operator::I64AtomicStore8 { .. }
-            | Operator::I64AtomicStore16 { .. }
-            | Operator::I64AtomicStore32 { .. } => 10 + 2,
-            Operator::F32Const { .. }
-            | Operator::F64Const { .. }
-            | Operator::I32Const { .. }
-            | Operator::I64Const { .. } => 1,
-            Operator::F32ConvertI32S
-            | Operator::F32ConvertI32U
-            | Operator::F32ConvertI64S
-            | Operator::F32ConvertI64U
-            | Operator::F64ConvertI32S
-            | Operator::F64ConvertI32U
-            | Operator::F64ConvertI64S
-            | Operator::F64ConvertI64U
-            | Operator::I32ReinterpretF32
-            | Operator::I64ReinterpretF64
-            | Operator::F32ReinterpretI32
-            | Operator::F64ReinterpretI64
-            | Operator::I32WrapI64
-            | Operator::I32Extend8S
-            | Operator::I32Extend16S
-            | Operator::I64Extend8S
-            | Operator::I64Extend16S
-            | Operator::I64Extend32S
-            | Operator::I64ExtendI32U
-            | Operator::I64ExtendI32S
-            | Operator::F32Trunc
-            | Operator::F64Trunc
-            | Operator::I32TruncF32S
-            | Operator::I32TruncF32U
-            | Operator::I32TruncF64S
-            | Operator::I32TruncF64U
-            | Operator::I32TruncSatF32S
-            | Operator::I32TruncSatF32U
-            | Operator::I32TruncSatF64S
-            | Operator::I32TruncSatF64U
-            | Operator::I64TruncF32S
-            | Operator::I64TruncF32U
-            | Operator::I64TruncF64S
-            | Operator::I64TruncF64U
-            | Operator::I64TruncSatF32S
-            | Operator::I64TruncSatF32U
-            | Operator::I64TruncSatF64S
-            | Operator::I64TruncSatF64U
-            | Operator::F32DemoteF64
-            | Operator::F64PromoteF32
-            | Operator::I32Popcnt
-            | Operator::I64Popcnt
-            | Operator::I32Clz
-            | Operator::I32Ctz
-            | Operator::I64Clz
-            | Operator::I64Ctz
-            | Operator::F32Neg
-            | Operator::F64Neg
-            | Operator::F32Abs
-            | Operator::F64Abs
-            | Operator::F32Ceil
-            | Operator::F64Ceil
-            | Operator::F32Floor
-            | Operator::F64Floor
-            | Operator::F32Nearest
-            | Operator::F64Nearest
-            | Operator::I32Eqz
-            | Operator::I64Eqz => 1,
-            Operator::F32Sqrt | Operator::F64Sqrt => 2,
-            Operator::F32x4Splat
-            | Operator::F64x2Splat
-            | Operator::I16x8Splat
-            | Operator::I32x4Splat
-            | Operator::I64x2Splat
-            | Operator::I8x16Splat
-            | Operator::V128Not
-            | Operator::V128AnyTrue
-            | Operator::F32x4Abs
-            | Operator::F32x4Neg
-            | Operator::F32x4Sqrt
-            | Operator::F32x4Ceil
-            | Operator::F32x4Floor
-            | Operator::F32x4Trunc
-            | Operator::F32x4Nearest
-            | Operator::F64x2Abs
-            | Operator::F64x2Neg
-            | Operator::F64x2Sqrt
-            | Operator::F64x2Ceil
-            | Operator::F64x2Floor
-            | Operator::F64x2Trunc
-            | Operator::F64x2Nearest
-            | Operator::I8x16Abs
-            | Operator::I8x16Neg
-            | Operator::I8x16AllTrue
-            | Operator::I8x16Bitmask
-            | Operator::I8x16Popcnt
-            | Operator::I16x8Abs
-            | Operator::I16x8Neg
-            | Operator::I16x8AllTrue
-            | Operator::I16x8Bitmask
-            | Operator::I32x4Abs
-            | Operator::I32x4Neg
-            | Operator::I32x4AllTrue
-            | Operator::I32x4Bitmask
-            | Operator::I64x2Abs
-            | Operator::I64x2Neg
-            | Operator::I64x2AllTrue
-            | Operator::I64x2Bitmask
-            | Operator::F32x4ConvertI32x4S
-            | Operator::F32x4ConvertI32x4U
-            | Operator::I32x4TruncSatF32x4S
-            | Operator::I32x4TruncSatF32x4U
-            | Operator::F64x2ConvertLowI32x4S
-            | Operator::F64x2ConvertLowI32x4U
-            | Operator::I32x4TruncSatF64x2SZero
-            | Operator::I32x4TruncSatF64x2UZero
-            | Operator::I16x8ExtAddPairwiseI8x16S
-            | Operator::I16x8ExtAddPairwiseI8x16U
-            | Operator::I32x4ExtAddPairwiseI16x8S
-            | Operator::I32x4ExtAddPairwiseI16x8U
-            | Operator::I16x8ExtendHighI8x16S
-            | Operator::I16x8ExtendLowI8x16S
-            | Operator::I16x8ExtendHighI8x16U
-            | Operator::I16x8ExtendLowI8x16U
-            | Operator::I32x4ExtendHighI16x8S
-            | Operator::I32x4ExtendLowI16x8S
-            | Operator::I32x4ExtendHighI16x8U
-            | Operator::I32x4ExtendLowI16x8U
-            | Operator::I64x2ExtendHighI32x4S
-            | Operator::I64x2ExtendLowI32x4S
-            | Operator::I64x2ExtendHighI32x4U
-            | Operator::I64x2ExtendLowI32x4U
-            | Operator::F32x4DemoteF64x2Zero
-            | Operator::F64x2PromoteLowF32x4
-            | Operator::I32x4RelaxedTruncSatF32x4S
-            | Operator::I32x4RelaxedTruncSatF32x4U
-            | Operator::I32x4RelaxedTruncSatF64x2SZero
-            | Operator::I32x4RelaxedTruncSatF64x2UZero => 1,
-            Operator::I32Add
-            | Operator::I32Sub
-            | Operator::I64Add
-            | Operator::I64Sub
-            | Operator::F32Add
-            | Operator::F32Sub
-            | Operator::F64Add
-            | Operator::F64Sub => 1,
-            Operator::I32Mul | Operator::I64Mul | Operator::F32Mul | Operator::F64Mul => 2,
-            Operator::I32DivS
-            | Operator::I32DivU
-            | Operator::I32RemS
-            | Operator::I32RemU
-            | Operator::I64DivS
-            | Operator::I64DivU
-            | Operator::I64RemS
-            | Operator::I64RemU
-            | Operator::F32Div
-            | Operator::F64Div => 3,
-            Operator::I32And
-            | Operator::I32Or
-            | Operator::I32Xor
-            | Operator::I32Shl
-            | Operator::I32ShrS
-            | Operator::I32ShrU
-            | Operator::I32Rotl
-            | Operator::I32Rotr
-            | Operator::I64And
-            | Operator::I64Or
-            | Operator::I64Xor
-            | Operator::I64Shl
-            | Operator::I64ShrS
-            | Operator::I64ShrU
-            | Operator::I64Rotl
-            | Operator::I64Rotr => 1,
-            Operator::F32Copysign | Operator::F64Copysign => 1,
-            Operator::F32Min | Operator::F32Max | Operator::F64Min | Operator::F64Max => 1,
-            Operator::I32Eq
-            | Operator::I32Ne
-            | Operator::I32LtS
-            | Operator::I32LtU
-            | Operator::I32LeS
-            | Operator::I32LeU
-            | Operator::I32GtS
-            | Operator::I32GtU
-            | Operator::I32GeS
-            | Operator::I32GeU
-            | Operator::I64Eq
-            | Operator::I64Ne
-            | Operator::I64LtS
-            | Operator::I64LtU
-            | Operator::I64LeS
-            | Operator::I64LeU
-            | Operator::I64GtS
-            | Operator::I64GtU
-            | Operator::I64GeS
-            | Operator::I64GeU
-            | Operator::F32Eq
-            | Operator::F32Ne
-            | Operator::F32Lt
-            | Operator::F32Le
-            | Operator::F32Gt
-            | Operator::F32Ge
-            | Operator::F64Eq
-            | Operator::F64Ne
-            | Operator::F64Lt
-            | Operator::F64Le
-            | Operator::F64Gt
-            | Operator::F64Ge => 1,
-            Operator::Block { .. }
-            | Operator::Loop { .. }
-            | Operator::If { .. }
-            | Operator::Else
-            | Operator::End
-            | Operator::Br { .. }
-            | Operator::BrIf { .. }
-            | Operator::BrTable { .. }
-            | Operator::Select => 1,
-            Operator::MemoryGrow { .. } | Operator::MemorySize { .. } => 1,
-            Operator::MemoryInit { .. }
-            | Operator::MemoryCopy { .. }
-            | Operator::MemoryFill { .. } => 6,
-            Operator::Return
-            | Operator::Unreachable
-            | Operator::Nop
-            | Operator::Drop
-            | Operator::Try { .. } => 0,
-            Operator::Call { .. } => 4,
-            Operator::CallIndirect { .. } => 6,
-            Operator::DataDrop { .. } => 5,
-            Operator::Throw { .. } => 100,
-            _ => {
-                eprintln!("Penalty Instruction [{:?}]", &operator);
-                1000
+        let name = operator_name(&operator);
+        let cost = match listed_cost(&name) {
+            Some(cost) => cost,
+            None => {
+                tracing::warn!("Penalty Instruction [{:?}]", &operator);
+                PENALTY_COST
             }
         };
 
-        // Add 1 to the count of the current operator, do static analysis
-        let x = format!("{:?}", operator);
-        let name = x.split_whitespace().next().unwrap();
-        let mut operation_counts = self.operation_counts.lock().unwrap();
-        operation_counts
-            .entry(name.to_string())
-            .and_modify(|counter| *counter += 1)
-            .or_insert(1);
+        // Flush the accumulated cost before it would overflow the i64 constant in the injected
+        // instructions, so a huge straight-line block is charged in several steps.
+        if overflows_constant(self.accumulated_cost, cost) {
+            self.flush_accumulated_cost(state);
+        }
+        self.accumulated_cost += cost;
+
+        // The counters are unlocked before the cost is flushed below, which borrows self mutably
+        {
+            // Add 1 to the count of the current operator, do static analysis
+            let mut operation_counts = self.operation_counts.lock().unwrap();
+            operation_counts
+                .entry(name.clone())
+                .and_modify(|counter| *counter += 1)
+                .or_insert(1);
+
+            // Add the cost of the current operator to its total
+            let mut operation_costs = self.operation_costs.lock().unwrap();
+            *operation_costs.entry(name).or_insert(0) += cost;
+        }
 
         // Possible sources and targets of a branch. Finalize the cost of the previous basic block and perform necessary checks.
         match operator {
@@ -459,56 +448,882 @@ impl FunctionMiddleware for FunctionCost {
             | Operator::CallIndirect { .. } // function call - branch source
             | Operator::Return // end of function - branch source
             => {
-                if self.accumulated_cost > 0 {
-                    state.extend(&[
-                        // if unsigned(globals[remaining_points_index]) < unsigned(self.accumulated_cost) { throw(); }
-                        Operator::GlobalGet { global_index: self.global_indexes.remaining_points().as_u32() },
-                        Operator::I64Const { value: self.accumulated_cost as i64 },
-                        Operator::I64LtU,
-                        Operator::If { blockty: WpTypeOrFuncType::Empty },
-                        Operator::I32Const { value: 1 },
-                        Operator::GlobalSet { global_index: self.global_indexes.points_exhausted().as_u32() },
-                        Operator::Unreachable,
-                        Operator::End,
-
-                        // globals[remaining_points_index] -= self.accumulated_cost;
-                        Operator::GlobalGet { global_index: self.global_indexes.remaining_points().as_u32() },
-                        Operator::I64Const { value: self.accumulated_cost as i64 },
-                        Operator::I64Sub,
-                        Operator::GlobalSet { global_index: self.global_indexes.remaining_points().as_u32() },
-                    ]);
-
-                    self.accumulated_cost = 0;
-                }
+                self.flush_accumulated_cost(state);
             }
             _ => {}
         }
+
+        // Bulk memory and table operations touch a number of bytes or entries only known at
+        // runtime, charge them by the length operand on the top of the stack before the operation
+        // executes.
+        let per_point = match operator {
+            Operator::MemoryInit { .. }
+            | Operator::MemoryCopy { .. }
+            | Operator::MemoryFill { .. } => Some(BULK_MEMORY_BYTES_PER_POINT),
+            Operator::TableGrow { .. }
+            | Operator::TableInit { .. }
+            | Operator::TableCopy { .. }
+            | Operator::TableFill { .. } => Some(BULK_TABLE_ENTRIES_PER_POINT),
+            _ => None,
+        };
+        if let Some(per_point) = per_point {
+            state.extend(&[
+                // globals[scratch_index] = pop(); // length
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                // if unsigned(globals[remaining_points_index]) < unsigned(length / rate) { throw(); }
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                Operator::I64ExtendI32U,
+                Operator::I64Const {
+                    value: per_point as i64,
+                },
+                Operator::I64DivU,
+                Operator::I64LtU,
+                Operator::If {
+                    blockty: WpTypeOrFuncType::Empty,
+                },
+                Operator::I32Const { value: 1 },
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.points_exhausted().as_u32(),
+                },
+                Operator::Unreachable,
+                Operator::End,
+                // globals[remaining_points_index] -= length / rate;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                Operator::I64ExtendI32U,
+                Operator::I64Const {
+                    value: per_point as i64,
+                },
+                Operator::I64DivU,
+                Operator::I64Sub,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.remaining_points().as_u32(),
+                },
+                // globals[function_cost_index] += length / rate;
+                Operator::GlobalGet {
+                    global_index: self.function_cost_index.as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                Operator::I64ExtendI32U,
+                Operator::I64Const {
+                    value: per_point as i64,
+                },
+                Operator::I64DivU,
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: self.function_cost_index.as_u32(),
+                },
+                // push(globals[scratch_index]); // length
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+            ]);
+        }
+
+        // Count the `memory.grow` operations, whose effect is only known at runtime.
+        if let Operator::MemoryGrow { .. } = operator {
+            state.extend(&[
+                // globals[memory_grows_index] += 1;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.memory_grows().as_u32(),
+                },
+                Operator::I64Const { value: 1 },
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.memory_grows().as_u32(),
+                },
+            ]);
+        }
+
+        let grows = matches!(operator, Operator::MemoryGrow { .. });
         state.push_operator(operator);
 
+        // Count the `memory.grow` operations that failed, which return -1 instead of trapping, so
+        // a program running out of memory can be told apart from other traps.
+        if grows {
+            state.extend(&[
+                // globals[scratch_index] = pop(); // previous size, or -1
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                // globals[failed_memory_grows_index] += globals[scratch_index] == -1;
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.failed_memory_grows().as_u32(),
+                },
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+                Operator::I32Const { value: -1 },
+                Operator::I32Eq,
+                Operator::I64ExtendI32U,
+                Operator::I64Add,
+                Operator::GlobalSet {
+                    global_index: self.global_indexes.failed_memory_grows().as_u32(),
+                },
+                // push(globals[scratch_index]); // previous size, or -1
+                Operator::GlobalGet {
+                    global_index: self.global_indexes.scratch().as_u32(),
+                },
+            ]);
+        }
+
         Ok(())
     }
 }
 
-pub fn get_remaining_points(ctx: &mut impl AsStoreMut, instance: &Instance) -> CostPoints {
+/// The cost of each operator, by the name of its `Operator` variant. Operators missing from the
+/// table cost `PENALTY_COST`. Bulk memory operations also cost one point per
+/// `BULK_MEMORY_BYTES_PER_POINT` bytes they touch at runtime, and bulk table operations one point
+/// per `BULK_TABLE_ENTRIES_PER_POINT` entries.
+/// Reference: https://nemequ.github.io/waspr/instructions
+/// Reference: https://github.com/WebAssembly/binaryen/blob/main/src/ir/cost.h
+pub const OPERATOR_COSTS: &[(&str, u64)] = &[
+    ("LocalGet", 0),
+    ("LocalSet", 1),
+    ("LocalTee", 1),
+    ("GlobalGet", 1),
+    ("GlobalSet", 2),
+    ("F32Load", 1),
+    ("F64Load", 1),
+    ("I32Load", 1),
+    ("I64Load", 1),
+    ("I32Load8S", 1),
+    ("I32Load8U", 1),
+    ("I32Load16S", 1),
+    ("I32Load16U", 1),
+    ("I64Load8S", 1),
+    ("I64Load8U", 1),
+    ("I64Load16S", 1),
+    ("I64Load16U", 1),
+    ("I64Load32S", 1),
+    ("I64Load32U", 1),
+    ("I32AtomicLoad", 10 + 1),
+    ("I32AtomicLoad8U", 10 + 1),
+    ("I32AtomicLoad16U", 10 + 1),
+    ("I64AtomicLoad", 10 + 1),
+    ("I64AtomicLoad8U", 10 + 1),
+    ("I64AtomicLoad16U", 10 + 1),
+    ("I64AtomicLoad32U", 10 + 1),
+    ("F32Store", 2),
+    ("F64Store", 2),
+    ("I32Store", 2),
+    ("I64Store", 2),
+    ("I32Store8", 2),
+    ("I32Store16", 2),
+    ("I64Store8", 2),
+    ("I64Store16", 2),
+    ("I64Store32", 2),
+    ("I32AtomicStore", 10 + 2),
+    ("I32AtomicStore8", 10 + 2),
+    ("I32AtomicStore16", 10 + 2),
+    ("I64AtomicStore", 10 + 2),
+    ("I64AtomicStore8", 10 + 2),
+    ("I64AtomicStore16", 10 + 2),
+    ("I64AtomicStore32", 10 + 2),
+    ("F32Const", 1),
+    ("F64Const", 1),
+    ("I32Const", 1),
+    ("I64Const", 1),
+    ("F32ConvertI32S", 1),
+    ("F32ConvertI32U", 1),
+    ("F32ConvertI64S", 1),
+    ("F32ConvertI64U", 1),
+    ("F64ConvertI32S", 1),
+    ("F64ConvertI32U", 1),
+    ("F64ConvertI64S", 1),
+    ("F64ConvertI64U", 1),
+    ("I32ReinterpretF32", 1),
+    ("I64ReinterpretF64", 1),
+    ("F32ReinterpretI32", 1),
+    ("F64ReinterpretI64", 1),
+    ("I32WrapI64", 1),
+    ("I32Extend8S", 1),
+    ("I32Extend16S", 1),
+    ("I64Extend8S", 1),
+    ("I64Extend16S", 1),
+    ("I64Extend32S", 1),
+    ("I64ExtendI32U", 1),
+    ("I64ExtendI32S", 1),
+    ("F32Trunc", 1),
+    ("F64Trunc", 1),
+    ("I32TruncF32S", 1),
+    ("I32TruncF32U", 1),
+    ("I32TruncF64S", 1),
+    ("I32TruncF64U", 1),
+    ("I32TruncSatF32S", 1),
+    ("I32TruncSatF32U", 1),
+    ("I32TruncSatF64S", 1),
+    ("I32TruncSatF64U", 1),
+    ("I64TruncF32S", 1),
+    ("I64TruncF32U", 1),
+    ("I64TruncF64S", 1),
+    ("I64TruncF64U", 1),
+    ("I64TruncSatF32S", 1),
+    ("I64TruncSatF32U", 1),
+    ("I64TruncSatF64S", 1),
+    ("I64TruncSatF64U", 1),
+    ("F32DemoteF64", 1),
+    ("F64PromoteF32", 1),
+    ("I32Popcnt", 1),
+    ("I64Popcnt", 1),
+    ("I32Clz", 1),
+    ("I32Ctz", 1),
+    ("I64Clz", 1),
+    ("I64Ctz", 1),
+    ("F32Neg", 1),
+    ("F64Neg", 1),
+    ("F32Abs", 1),
+    ("F64Abs", 1),
+    ("F32Ceil", 1),
+    ("F64Ceil", 1),
+    ("F32Floor", 1),
+    ("F64Floor", 1),
+    ("F32Nearest", 1),
+    ("F64Nearest", 1),
+    ("I32Eqz", 1),
+    ("I64Eqz", 1),
+    ("F32Sqrt", 2),
+    ("F64Sqrt", 2),
+    ("F32x4Splat", 1),
+    ("F64x2Splat", 1),
+    ("I16x8Splat", 1),
+    ("I32x4Splat", 1),
+    ("I64x2Splat", 1),
+    ("I8x16Splat", 1),
+    ("V128Not", 1),
+    ("V128AnyTrue", 1),
+    ("F32x4Abs", 1),
+    ("F32x4Neg", 1),
+    ("F32x4Sqrt", 1),
+    ("F32x4Ceil", 1),
+    ("F32x4Floor", 1),
+    ("F32x4Trunc", 1),
+    ("F32x4Nearest", 1),
+    ("F64x2Abs", 1),
+    ("F64x2Neg", 1),
+    ("F64x2Sqrt", 1),
+    ("F64x2Ceil", 1),
+    ("F64x2Floor", 1),
+    ("F64x2Trunc", 1),
+    ("F64x2Nearest", 1),
+    ("I8x16Abs", 1),
+    ("I8x16Neg", 1),
+    ("I8x16AllTrue", 1),
+    ("I8x16Bitmask", 1),
+    ("I8x16Popcnt", 1),
+    ("I16x8Abs", 1),
+    ("I16x8Neg", 1),
+    ("I16x8AllTrue", 1),
+    ("I16x8Bitmask", 1),
+    ("I32x4Abs", 1),
+    ("I32x4Neg", 1),
+    ("I32x4AllTrue", 1),
+    ("I32x4Bitmask", 1),
+    ("I64x2Abs", 1),
+    ("I64x2Neg", 1),
+    ("I64x2AllTrue", 1),
+    ("I64x2Bitmask", 1),
+    ("F32x4ConvertI32x4S", 1),
+    ("F32x4ConvertI32x4U", 1),
+    ("I32x4TruncSatF32x4S", 1),
+    ("I32x4TruncSatF32x4U", 1),
+    ("F64x2ConvertLowI32x4S", 1),
+    ("F64x2ConvertLowI32x4U", 1),
+    ("I32x4TruncSatF64x2SZero", 1),
+    ("I32x4TruncSatF64x2UZero", 1),
+    ("I16x8ExtAddPairwiseI8x16S", 1),
+    ("I16x8ExtAddPairwiseI8x16U", 1),
+    ("I32x4ExtAddPairwiseI16x8S", 1),
+    ("I32x4ExtAddPairwiseI16x8U", 1),
+    ("I16x8ExtendHighI8x16S", 1),
+    ("I16x8ExtendLowI8x16S", 1),
+    ("I16x8ExtendHighI8x16U", 1),
+    ("I16x8ExtendLowI8x16U", 1),
+    ("I32x4ExtendHighI16x8S", 1),
+    ("I32x4ExtendLowI16x8S", 1),
+    ("I32x4ExtendHighI16x8U", 1),
+    ("I32x4ExtendLowI16x8U", 1),
+    ("I64x2ExtendHighI32x4S", 1),
+    ("I64x2ExtendLowI32x4S", 1),
+    ("I64x2ExtendHighI32x4U", 1),
+    ("I64x2ExtendLowI32x4U", 1),
+    ("F32x4DemoteF64x2Zero", 1),
+    ("F64x2PromoteLowF32x4", 1),
+    ("I32x4RelaxedTruncSatF32x4S", 1),
+    ("I32x4RelaxedTruncSatF32x4U", 1),
+    ("I32x4RelaxedTruncSatF64x2SZero", 1),
+    ("I32x4RelaxedTruncSatF64x2UZero", 1),
+    ("I32Add", 1),
+    ("I32Sub", 1),
+    ("I64Add", 1),
+    ("I64Sub", 1),
+    ("F32Add", 1),
+    ("F32Sub", 1),
+    ("F64Add", 1),
+    ("F64Sub", 1),
+    ("I32Mul", 2),
+    ("I64Mul", 2),
+    ("F32Mul", 2),
+    ("F64Mul", 2),
+    ("I32DivS", 3),
+    ("I32DivU", 3),
+    ("I32RemS", 3),
+    ("I32RemU", 3),
+    ("I64DivS", 3),
+    ("I64DivU", 3),
+    ("I64RemS", 3),
+    ("I64RemU", 3),
+    ("F32Div", 3),
+    ("F64Div", 3),
+    ("I32And", 1),
+    ("I32Or", 1),
+    ("I32Xor", 1),
+    ("I32Shl", 1),
+    ("I32ShrS", 1),
+    ("I32ShrU", 1),
+    ("I32Rotl", 1),
+    ("I32Rotr", 1),
+    ("I64And", 1),
+    ("I64Or", 1),
+    ("I64Xor", 1),
+    ("I64Shl", 1),
+    ("I64ShrS", 1),
+    ("I64ShrU", 1),
+    ("I64Rotl", 1),
+    ("I64Rotr", 1),
+    ("F32Copysign", 1),
+    ("F64Copysign", 1),
+    ("F32Min", 1),
+    ("F32Max", 1),
+    ("F64Min", 1),
+    ("F64Max", 1),
+    ("I32Eq", 1),
+    ("I32Ne", 1),
+    ("I32LtS", 1),
+    ("I32LtU", 1),
+    ("I32LeS", 1),
+    ("I32LeU", 1),
+    ("I32GtS", 1),
+    ("I32GtU", 1),
+    ("I32GeS", 1),
+    ("I32GeU", 1),
+    ("I64Eq", 1),
+    ("I64Ne", 1),
+    ("I64LtS", 1),
+    ("I64LtU", 1),
+    ("I64LeS", 1),
+    ("I64LeU", 1),
+    ("I64GtS", 1),
+    ("I64GtU", 1),
+    ("I64GeS", 1),
+    ("I64GeU", 1),
+    ("F32Eq", 1),
+    ("F32Ne", 1),
+    ("F32Lt", 1),
+    ("F32Le", 1),
+    ("F32Gt", 1),
+    ("F32Ge", 1),
+    ("F64Eq", 1),
+    ("F64Ne", 1),
+    ("F64Lt", 1),
+    ("F64Le", 1),
+    ("F64Gt", 1),
+    ("F64Ge", 1),
+    ("Block", 1),
+    ("Loop", 1),
+    ("If", 1),
+    ("Else", 1),
+    ("End", 1),
+    ("Br", 1),
+    ("BrIf", 1),
+    ("BrTable", 1),
+    ("Select", 1),
+    ("MemoryGrow", 1),
+    ("MemorySize", 1),
+    ("MemoryInit", 6),
+    ("MemoryCopy", 6),
+    ("MemoryFill", 6),
+    ("TableGet", 1),
+    ("TableSize", 1),
+    ("TableSet", 2),
+    ("TableGrow", 6),
+    ("TableInit", 6),
+    ("TableCopy", 6),
+    ("TableFill", 6),
+    ("RefNull", 1),
+    ("RefIsNull", 1),
+    ("RefFunc", 1),
+    ("Return", 0),
+    ("Unreachable", 0),
+    ("Nop", 0),
+    ("Drop", 0),
+    ("Try", 0),
+    ("Call", 4),
+    ("CallIndirect", 6),
+    ("DataDrop", 5),
+    ("ElemDrop", 5),
+    ("Throw", 100),
+];
+
+/// Whether adding the cost to the accumulated cost would overflow the i64 constant it is charged
+/// with.
+fn overflows_constant(accumulated_cost: u64, cost: u64) -> bool {
+    accumulated_cost.saturating_add(cost) > i64::MAX as u64
+}
+
+/// The name of an operator, the name of its `Operator` variant, as used in `OPERATOR_COSTS` and
+/// the operation counts.
+pub fn operator_name(operator: &Operator) -> String {
+    let name = format!("{:?}", operator);
+    match name.split_whitespace().next() {
+        Some(variant) => variant.to_string(),
+        None => name,
+    }
+}
+
+/// The cost of an operator listed in `OPERATOR_COSTS`, by name.
+pub fn listed_cost(name: &str) -> Option<u64> {
+    static COSTS: OnceLock<HashMap<&str, u64>> = OnceLock::new();
+    COSTS
+        .get_or_init(|| OPERATOR_COSTS.iter().copied().collect())
+        .get(name)
+        .copied()
+}
+
+/// The static cost of an operator, as charged by the Cost middleware, `PENALTY_COST` if it is
+/// missing from the cost table.
+pub fn operator_cost(operator: &Operator) -> u64 {
+    listed_cost(&operator_name(operator)).unwrap_or(PENALTY_COST)
+}
+
+/// Get the category of an operator from its name, as recorded in `operation_counts`.
+pub fn operation_category(name: &str) -> &'static str {
+    const SIMD_PREFIXES: [&str; 7] = ["V128", "I8x16", "I16x8", "I32x4", "I64x2", "F32x4", "F64x2"];
+    const CONVERSIONS: [&str; 6] = [
+        "Convert",
+        "Wrap",
+        "Extend",
+        "Demote",
+        "Promote",
+        "Reinterpret",
+    ];
+    const COMPARISONS: [&str; 15] = [
+        "Eqz", "Eq", "Ne", "Lt", "LtS", "LtU", "Gt", "GtS", "GtU", "Le", "LeS", "LeU", "Ge", "GeS",
+        "GeU",
+    ];
+
+    if name.contains("Atomic") {
+        return "atomic";
+    }
+    if SIMD_PREFIXES.iter().any(|prefix| name.starts_with(prefix)) {
+        return "simd";
+    }
+
+    match name {
+        "LocalGet" | "LocalSet" | "LocalTee" | "GlobalGet" | "GlobalSet" => return "variable",
+        "Drop" | "Select" | "TypedSelect" => return "parametric",
+        "Block" | "Loop" | "If" | "Else" | "End" | "Br" | "BrIf" | "BrTable" | "Return"
+        | "Call" | "CallIndirect" | "ReturnCall" | "ReturnCallIndirect" | "Unreachable" | "Nop"
+        | "Try" | "Catch" | "CatchAll" | "Throw" | "Rethrow" | "Delegate" => return "control",
+        "DataDrop" => return "memory",
+        "ElemDrop" => return "table",
+        _ => {}
+    }
+
+    if name.contains("Load") || name.contains("Store") || name.starts_with("Memory") {
+        return "memory";
+    }
+    if name.starts_with("Table") || name.starts_with("Ref") {
+        return "table";
+    }
+    if name.ends_with("Const") {
+        return "constant";
+    }
+
+    // Numeric operators are named by their type followed by the operation, e.g. `I32Add`.
+    let numeric = ["I32", "I64", "F32", "F64"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix));
+    match numeric {
+        // `F32Trunc` rounds a float, while `I32TruncF32S` converts it to an integer.
+        Some(op) if op.starts_with("Trunc") && op != "Trunc" => "conversion",
+        Some(op)
+            if CONVERSIONS
+                .iter()
+                .any(|conversion| op.starts_with(conversion)) =>
+        {
+            "conversion"
+        }
+        Some(op) if COMPARISONS.contains(&op) => "comparison",
+        Some(_) => "arithmetic",
+        None => "other",
+    }
+}
+
+/// Roll the operation counts up into the categories of [`operation_category`].
+pub fn categorize_operations(operation_counts: &HashMap<String, u64>) -> HashMap<String, u64> {
+    let mut categories = HashMap::new();
+    for (name, count) in operation_counts {
+        *categories
+            .entry(operation_category(name).to_string())
+            .or_insert(0) += count;
+    }
+    categories
+}
+
+pub fn get_remaining_points(
+    ctx: &mut impl AsStoreMut,
+    instance: &Instance,
+    prefix: &str,
+) -> CostPoints {
+    let exhausted_export = points_exhausted_export(prefix);
     let exhausted: i32 = instance
         .exports
-        .get_global("compilet_cost_points_exhausted")
-        .expect("Can't get `compilet_cost_points_exhausted` from Instance")
+        .get_global(&exhausted_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", exhausted_export))
         .get(ctx)
         .try_into()
-        .expect("`compilet_cost_points_exhausted` from Instance has wrong type");
+        .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", exhausted_export));
 
     if exhausted > 0 {
         return CostPoints::Exhausted;
     }
 
+    let remaining_export = remaining_points_export(prefix);
     let points = instance
         .exports
-        .get_global("compilet_cost_remaining_points")
-        .expect("Can't get `compilet_cost_remaining_points` from Instance")
+        .get_global(&remaining_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", remaining_export))
         .get(ctx)
         .try_into()
-        .expect("`compilet_cost_remaining_points` from Instance has wrong type");
+        .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", remaining_export));
 
     CostPoints::Remaining(points)
 }
+
+/// Set the remaining points of the instance and clear the exhausted flag, so the
+/// execution can be restarted with a fresh budget. Points above `i64::MAX`, the most the global
+/// can hold, are clamped to it.
+pub fn set_remaining_points(
+    ctx: &mut impl AsStoreMut,
+    instance: &Instance,
+    prefix: &str,
+    points: u64,
+) {
+    let remaining_export = remaining_points_export(prefix);
+    let points = i64::try_from(points).unwrap_or(i64::MAX);
+    instance
+        .exports
+        .get_global(&remaining_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", remaining_export))
+        .set(ctx, Value::I64(points))
+        .unwrap_or_else(|_| panic!("Can't set `{}` in Instance", remaining_export));
+
+    reset_exhausted(ctx, instance, prefix);
+}
+
+/// Clear the exhausted flag of the instance without touching the remaining points.
+pub fn reset_exhausted(ctx: &mut impl AsStoreMut, instance: &Instance, prefix: &str) {
+    let exhausted_export = points_exhausted_export(prefix);
+    instance
+        .exports
+        .get_global(&exhausted_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", exhausted_export))
+        .set(ctx, Value::I32(0))
+        .unwrap_or_else(|_| panic!("Can't set `{}` in Instance", exhausted_export));
+}
+
+/// Get the number of `memory.grow` operations the instance executed.
+pub fn get_memory_grows(ctx: &mut impl AsStoreMut, instance: &Instance, prefix: &str) -> u64 {
+    let memory_grows_export = memory_grows_export(prefix);
+    instance
+        .exports
+        .get_global(&memory_grows_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", memory_grows_export))
+        .get(ctx)
+        .try_into()
+        .unwrap_or_else(|_| panic!("`{}` from Instance has wrong type", memory_grows_export))
+}
+
+/// Get the number of `memory.grow` operations of the instance that failed.
+pub fn get_failed_memory_grows(
+    ctx: &mut impl AsStoreMut,
+    instance: &Instance,
+    prefix: &str,
+) -> u64 {
+    let failed_memory_grows_export = failed_memory_grows_export(prefix);
+    instance
+        .exports
+        .get_global(&failed_memory_grows_export)
+        .unwrap_or_else(|_| panic!("Can't get `{}` from Instance", failed_memory_grows_export))
+        .get(ctx)
+        .try_into()
+        .unwrap_or_else(|_| {
+            panic!(
+                "`{}` from Instance has wrong type",
+                failed_memory_grows_export
+            )
+        })
+}
+
+/// Get the points spent by each local function, keyed by its local function index.
+/// Functions that never spent any points are omitted.
+pub fn get_function_costs(
+    ctx: &mut impl AsStoreMut,
+    instance: &Instance,
+    prefix: &str,
+) -> HashMap<u32, u64> {
+    let function_prefix = function_cost_export_prefix(prefix);
+    instance
+        .exports
+        .iter()
+        .filter_map(|(name, export)| {
+            let index = name.strip_prefix(&function_prefix)?.parse::<u32>().ok()?;
+            match export {
+                Extern::Global(global) => {
+                    let points: u64 = global.get(ctx).try_into().ok()?;
+                    Some((index, points))
+                }
+                _ => None,
+            }
+        })
+        .filter(|(_, points)| *points > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::run::Compiler;
+    use wasmer::{imports, CompilerConfig, Cranelift, Module, Singlepass, Store};
+
+    /// A function counting to 1000 in a loop, so it runs out of a small budget.
+    const SPIN: &str = r#"
+        (module
+            (func (export "run") (local i32)
+                (loop
+                    (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                    (br_if 0 (i32.lt_u (local.get 0) (i32.const 1000))))))
+    "#;
+
+    /// Instantiate the WAT module, metered with the given budget and without any imports.
+    fn instantiate(wat: &str, budget: u64) -> (Store, Instance) {
+        instantiate_with(wat, budget, Compiler::Cranelift)
+    }
+
+    /// Instantiate the WAT module like `instantiate`, compiled with the given compiler.
+    fn instantiate_with(wat: &str, budget: u64, compiler: Compiler) -> (Store, Instance) {
+        let metering = Arc::new(Cost::new(budget, DEFAULT_EXPORT_PREFIX));
+        let mut store = match compiler {
+            Compiler::Cranelift => {
+                let mut compiler = Cranelift::default();
+                compiler.push_middleware(metering);
+                Store::new(compiler)
+            }
+            Compiler::Singlepass => {
+                let mut compiler = Singlepass::default();
+                compiler.push_middleware(metering);
+                Store::new(compiler)
+            }
+        };
+        let module = Module::new(&store, wat::parse_str(wat).unwrap()).unwrap();
+        let instance = Instance::new(&mut store, &module, &imports! {}).unwrap();
+        (store, instance)
+    }
+
+    fn call_run(store: &mut Store, instance: &Instance) -> bool {
+        let run = instance.exports.get_function("run").unwrap();
+        run.call(store, &[]).is_ok()
+    }
+
+    /// Run the WAT module to completion with both compilers and return the points it spent,
+    /// which must not depend on the compiler.
+    fn spent(wat: &str) -> u64 {
+        let cost = spent_with(wat, Compiler::Cranelift);
+        assert_eq!(spent_with(wat, Compiler::Singlepass), cost);
+        cost
+    }
+
+    /// Run the WAT module to completion with the given compiler and return the points it spent.
+    fn spent_with(wat: &str, compiler: Compiler) -> u64 {
+        let budget = 1_000_000_000;
+        let (mut store, instance) = instantiate_with(wat, budget, compiler);
+        assert!(call_run(&mut store, &instance));
+        match get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX) {
+            CostPoints::Remaining(points) => budget - points,
+            CostPoints::Exhausted => panic!("the budget is exhausted"),
+        }
+    }
+
+    /// A module with one page of memory running the given body.
+    fn with_memory(body: &str) -> String {
+        format!(
+            r#"(module (memory 1) (func (export "run") (local i32) {}))"#,
+            body
+        )
+    }
+
+    #[test]
+    fn refilled_points_continue_the_execution() {
+        let (mut store, instance) = instantiate(SPIN, 100);
+        assert!(!call_run(&mut store, &instance));
+        assert_eq!(
+            get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX),
+            CostPoints::Exhausted
+        );
+
+        set_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX, 1_000_000);
+        assert_eq!(
+            get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX),
+            CostPoints::Remaining(1_000_000)
+        );
+        assert!(call_run(&mut store, &instance));
+        match get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX) {
+            CostPoints::Remaining(points) => assert!(points < 1_000_000),
+            CostPoints::Exhausted => panic!("the refilled points are exhausted"),
+        }
+    }
+
+    #[test]
+    fn reset_clears_only_the_exhausted_flag() {
+        let (mut store, instance) = instantiate(SPIN, 100);
+        assert!(!call_run(&mut store, &instance));
+
+        reset_exhausted(&mut store, &instance, DEFAULT_EXPORT_PREFIX);
+        assert!(matches!(
+            get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX),
+            CostPoints::Remaining(_)
+        ));
+        // No points were added, so the next call runs out again
+        assert!(!call_run(&mut store, &instance));
+    }
+
+    #[test]
+    fn remaining_points_are_clamped() {
+        let (mut store, instance) = instantiate(SPIN, 100);
+        set_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX, u64::MAX);
+        assert_eq!(
+            get_remaining_points(&mut store, &instance, DEFAULT_EXPORT_PREFIX),
+            CostPoints::Remaining(i64::MAX as u64)
+        );
+    }
+
+    #[test]
+    fn bulk_memory_is_charged_by_the_byte() {
+        let fill = |len: u64| {
+            spent(&with_memory(&format!(
+                "(memory.fill (i32.const 0) (i32.const 0) (i32.const {}))",
+                len
+            )))
+        };
+        let copy = |len: u64| {
+            spent(&with_memory(&format!(
+                "(memory.copy (i32.const 0) (i32.const 32768) (i32.const {}))",
+                len
+            )))
+        };
+
+        assert_eq!(fill(32768) - fill(0), 32768 / BULK_MEMORY_BYTES_PER_POINT);
+        assert_eq!(copy(32768) - copy(0), 32768 / BULK_MEMORY_BYTES_PER_POINT);
+
+        // Filling the memory with a loop of stores costs more than with a single fill
+        let store_loop = spent(&with_memory(
+            r#"(loop
+                (i32.store8 (local.get 0) (i32.const 0))
+                (local.set 0 (i32.add (local.get 0) (i32.const 1)))
+                (br_if 0 (i32.lt_u (local.get 0) (i32.const 32768))))"#,
+        ));
+        assert!(fill(32768) < store_loop);
+    }
+
+    #[test]
+    fn accumulated_cost_fits_the_constant() {
+        let max = i64::MAX as u64;
+        assert!(!overflows_constant(0, PENALTY_COST));
+        assert!(!overflows_constant(max - 5, 5));
+        assert!(overflows_constant(max - 5, 6));
+        assert!(overflows_constant(max, 1));
+        assert!(overflows_constant(u64::MAX, PENALTY_COST));
+    }
+
+    #[test]
+    fn long_blocks_are_charged_exactly() {
+        let block = |len: usize| spent(&with_memory(&"(drop (i32.const 1))".repeat(len)));
+        let pair = listed_cost("I32Const").unwrap() + listed_cost("Drop").unwrap();
+        assert_eq!(block(50_000) - block(0), 50_000 * pair);
+    }
+
+    #[test]
+    fn table_operations_are_not_penalized() {
+        for name in [
+            "TableGet",
+            "TableSet",
+            "TableSize",
+            "TableGrow",
+            "TableFill",
+            "TableCopy",
+        ] {
+            assert!(listed_cost(name).is_some(), "{} has no cost", name);
+        }
+
+        let tables = r#"
+            (module
+                (table 4 funcref)
+                (func $f)
+                (elem declare func $f)
+                (func (export "run")
+                    (drop (table.grow (ref.null func) (i32.const 2)))
+                    (table.set (i32.const 0) (ref.func $f))
+                    (table.set (i32.const 1) (table.get (i32.const 0)))
+                    (table.fill (i32.const 2) (ref.func $f) (i32.const 2))
+                    (table.copy (i32.const 4) (i32.const 0) (i32.const 2))
+                    (drop (table.size))))
+        "#;
+        assert!(spent(tables) < PENALTY_COST);
+    }
+
+    #[test]
+    fn bulk_table_operations_are_charged_by_the_entry() {
+        let table = |body: String| {
+            spent(&format!(
+                r#"(module (table 65536 funcref) (func (export "run") {}))"#,
+                body
+            ))
+        };
+        let grow = |len: u64| {
+            table(format!(
+                "(drop (table.grow (ref.null func) (i32.const {})))",
+                len
+            ))
+        };
+        let fill = |len: u64| {
+            table(format!(
+                "(table.fill (i32.const 0) (ref.null func) (i32.const {}))",
+                len
+            ))
+        };
+        let copy = |len: u64| {
+            table(format!(
+                "(table.copy (i32.const 0) (i32.const 32768) (i32.const {}))",
+                len
+            ))
+        };
+
+        assert_eq!(grow(32768) - grow(0), 32768 / BULK_TABLE_ENTRIES_PER_POINT);
+        assert_eq!(fill(32768) - fill(0), 32768 / BULK_TABLE_ENTRIES_PER_POINT);
+        assert_eq!(copy(32768) - copy(0), 32768 / BULK_TABLE_ENTRIES_PER_POINT);
+    }
+}
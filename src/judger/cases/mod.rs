@@ -0,0 +1,65 @@
+use super::{JudgeSpec, Judger};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CasesJudgeSpec {
+    /// The cases, judged by their own judger against the same compiled module.
+    /// All cases must share the same cost, memory and time limits.
+    pub cases: Vec<JudgeSpec>,
+    /// The subtask of the spec, all specs of a subtask must pass to earn its points
+    pub subtask: Option<String>,
+    /// The points earned by passing the spec
+    pub points: Option<f64>,
+}
+
+impl CasesJudgeSpec {
+    pub async fn check_spec(&self) -> Result<(), String> {
+        let limits = match self.cases.first() {
+            Some(case) => case.limits(),
+            None => return Err("Must provide at least one case".to_string()),
+        };
+
+        for (index, case) in self.cases.iter().enumerate() {
+            if let JudgeSpec::Cases(_) = case {
+                return Err(format!("Invalid case {}, cases can not be nested", index));
+            }
+
+            // The cases run the module on its own, not against a checker
+            if let JudgeSpec::Interactive(_) = case {
+                return Err(format!(
+                    "Invalid case {}, cases can not be interactive",
+                    index
+                ));
+            }
+
+            if case.limits() != limits || case.time_limit() != self.time_limit() {
+                return Err(format!(
+                    "Invalid case {}, all cases must share the same cost, memory and time limits",
+                    index
+                ));
+            }
+
+            case.check_spec()
+                .await
+                .map_err(|e| format!("Invalid case {}, {}", index, e))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn limits(&self) -> (u64, u32) {
+        self.cases
+            .first()
+            .map(|case| case.limits())
+            .unwrap_or((0, 0))
+    }
+
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.cases.first().and_then(|case| case.time_limit())
+    }
+
+    pub fn scoring(&self) -> (Option<String>, Option<f64>) {
+        (self.subtask.clone(), self.points)
+    }
+}
@@ -0,0 +1,117 @@
+use super::{check_limits, run_helper, Input, Judger, Output, SpecCommon, SpecInput};
+use crate::clock::ClockMode;
+use crate::config::config;
+use crate::random::RandomMode;
+use crate::run;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckerJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The base64-encoded WebAssembly binary of the checker
+    pub checker: String,
+    /// The maximum cost of the checker
+    pub checker_cost: u64,
+    /// The maximum memory of the checker
+    pub checker_memory: u32,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+#[async_trait]
+impl Judger for CheckerJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        check_limits(self.checker_cost, self.checker_memory)?;
+        self.input.check()?;
+
+        if general_purpose::STANDARD
+            .decode(self.checker.as_bytes())
+            .is_err()
+        {
+            return Err("Invalid checker. Error decoding base64.".to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    /// Run the checker with the byte length of the input on the first line, followed by the
+    /// input and the output of the program on its stdin, as `run_checker` does.
+    async fn judge_output(&self, input: &Input, output: &Output) -> Result<(), String> {
+        run_checker(
+            &self.checker,
+            self.checker_cost,
+            self.checker_memory,
+            input,
+            output,
+        )
+        .await
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+/// Run a base64-encoded checker with the byte length of the input on the first line, followed by
+/// the input and the output of the program on its stdin. The output is accepted if the checker
+/// exits successfully and prints nothing or a first line of `AC`, anything else it prints is
+/// reported as the reason of the rejection. The checker is run by `run_helper`.
+pub async fn run_checker(
+    checker: &str,
+    cost: u64,
+    memory: u32,
+    input: &Input,
+    output: &Output,
+) -> Result<(), String> {
+    let wasm = general_purpose::STANDARD
+        .decode(checker.as_bytes())
+        .map_err(|_| "Invalid checker. Error decoding base64.".to_string())?
+        .into_boxed_slice();
+
+    let request = run::RunRequest {
+        wasm,
+        budget: cost,
+        mem: memory,
+        input: format!(
+            "{}\n{}{}",
+            input.stdin.len(),
+            input.stdin,
+            output.stdout_text()
+        ),
+        metering: true,
+        timeout: None,
+        random_mode: RandomMode::System,
+        clock_mode: ClockMode::System,
+        compiler: config().compiler,
+        canonicalize_nans: config().canonicalize_nans,
+        strict: config().strict,
+    };
+    let result = run_helper("Checker", request).await?;
+
+    let verdict = String::from_utf8_lossy(&result.stdout);
+    let verdict = verdict.trim();
+    if verdict.is_empty() || verdict.lines().next() == Some("AC") {
+        return Ok(());
+    }
+
+    Err(verdict.to_string())
+}
@@ -0,0 +1,184 @@
+use super::{Input, Judger, Output, SpecCommon, SpecInput};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FloatJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The expected output, compared token by token
+    pub expected_output: String,
+    /// The absolute tolerance of numeric tokens
+    pub eps_abs: f64,
+    /// The relative tolerance of numeric tokens, relative to the expected value
+    pub eps_rel: f64,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+#[async_trait]
+impl Judger for FloatJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        self.input.check()?;
+
+        if !(self.eps_abs >= 0.0 && self.eps_rel >= 0.0) {
+            return Err(format!(
+                "Invalid tolerance, got eps_abs {} and eps_rel {}, both must be non-negative",
+                self.eps_abs, self.eps_rel
+            ));
+        }
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        let mut expected = self.expected_output.split_ascii_whitespace();
+        let actual = output.stdout_text();
+        let mut actual = actual.split_ascii_whitespace();
+
+        let mut index = 0;
+        loop {
+            match (expected.next(), actual.next()) {
+                (None, None) => return Ok(()),
+                (Some(e), Some(a)) => self.compare(index, e, a)?,
+                (Some(e), None) => {
+                    return Err(format!(
+                        "Token mismatch at index {}. Expected `{}`, got end of output",
+                        index, e
+                    ))
+                }
+                (None, Some(a)) => {
+                    return Err(format!(
+                        "Token mismatch at index {}. Expected end of output, got `{}`",
+                        index, a
+                    ))
+                }
+            }
+            index += 1;
+        }
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+impl FloatJudgeSpec {
+    /// Compare two tokens, numerically within the tolerance if both are numbers, exactly otherwise.
+    /// NaN only matches NaN, and infinities only match infinities of the same sign.
+    fn compare(&self, index: usize, expected: &str, actual: &str) -> Result<(), String> {
+        let (e, a) = match (expected.parse::<f64>(), actual.parse::<f64>()) {
+            (Ok(e), Ok(a)) => (e, a),
+            _ if expected == actual => return Ok(()),
+            _ => {
+                return Err(format!(
+                    "Token mismatch at index {}. Expected `{}`, got `{}`",
+                    index, expected, actual
+                ))
+            }
+        };
+
+        if e.is_nan() || a.is_nan() || e.is_infinite() || a.is_infinite() {
+            if (e.is_nan() && a.is_nan()) || e == a {
+                return Ok(());
+            }
+            return Err(format!(
+                "Token mismatch at index {}. Expected `{}`, got `{}`",
+                index, expected, actual
+            ));
+        }
+
+        let error = (a - e).abs();
+        if error <= self.eps_abs.max(self.eps_rel * e.abs()) {
+            return Ok(());
+        }
+
+        Err(format!(
+            "Token mismatch at index {}. Expected `{}`, got `{}`, error {}",
+            index, expected, actual, error
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(expected_output: &str) -> FloatJudgeSpec {
+        FloatJudgeSpec {
+            input: SpecInput::default(),
+            expected_output: expected_output.to_string(),
+            eps_abs: 1e-6,
+            eps_rel: 1e-6,
+            common: SpecCommon::default(),
+        }
+    }
+
+    #[test]
+    fn nan_matches_only_nan() {
+        let spec = spec("");
+        assert!(spec.compare(0, "nan", "nan").is_ok());
+        assert!(spec.compare(0, "NaN", "nan").is_ok());
+        assert!(spec.compare(0, "nan", "0").is_err());
+        assert!(spec.compare(0, "0", "nan").is_err());
+    }
+
+    #[test]
+    fn infinities_match_only_the_same_sign() {
+        let spec = spec("");
+        assert!(spec.compare(0, "inf", "inf").is_ok());
+        assert!(spec.compare(0, "-inf", "-inf").is_ok());
+        assert!(spec.compare(0, "inf", "-inf").is_err());
+        assert!(spec.compare(0, "inf", "1e308").is_err());
+    }
+
+    #[test]
+    fn text_tokens_match_exactly() {
+        let spec = spec("");
+        assert!(spec.compare(0, "yes", "yes").is_ok());
+        assert!(spec.compare(0, "yes", "1").is_err());
+        assert!(spec.compare(0, "1", "yes").is_err());
+    }
+
+    #[rocket::async_test]
+    async fn mismatch_reports_index_and_error() {
+        let input = Input {
+            stdin: String::new(),
+        };
+        let output = |stdout: &str| Output {
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: String::new(),
+            verdict: None,
+        };
+        let spec = spec("case 1.5 2.0");
+
+        assert!(spec
+            .judge_output(&input, &output("case 1.5000001 2"))
+            .await
+            .is_ok());
+        assert_eq!(
+            spec.judge_output(&input, &output("case 1.5 2.5")).await,
+            Err("Token mismatch at index 2. Expected `2.0`, got `2.5`, error 0.5".to_string())
+        );
+        assert_eq!(
+            spec.judge_output(&input, &output("case 1.5")).await,
+            Err("Token mismatch at index 2. Expected `2.0`, got end of output".to_string())
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use super::checker::run_checker;
+use super::token::compare_tokens;
+use super::{check_limits, run_helper, Input, Judger, Output, SpecCommon};
+use crate::clock::{ClockMode, DEFAULT_CLOCK_INCREMENT, DEFAULT_CLOCK_START};
+use crate::config::config;
+use crate::random::RandomMode;
+use crate::run;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GeneratorJudgeSpec {
+    /// The base64-encoded WebAssembly binary of the generator, whose stdout is the input
+    pub generator: String,
+    /// The seed of the generator, on its stdin and seeding its random bytes
+    pub seed: u64,
+    /// The maximum cost of the generator
+    pub generator_cost: u64,
+    /// The maximum memory of the generator
+    pub generator_memory: u32,
+    /// The base64-encoded WebAssembly binary of the checker of the output
+    pub checker: Option<String>,
+    /// The base64-encoded WebAssembly binary of the reference solution, whose output is expected
+    pub reference: Option<String>,
+    /// The maximum cost of the checker or the reference solution
+    pub checker_cost: u64,
+    /// The maximum memory of the checker or the reference solution
+    pub checker_memory: u32,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+impl GeneratorJudgeSpec {
+    /// Run a helper module of the spec to completion with deterministic randomness and clocks,
+    /// so it prints the same output for the same seed, and return its stdout.
+    async fn run_seeded(
+        name: &str,
+        module: &str,
+        budget: u64,
+        mem: u32,
+        input: String,
+        seed: u64,
+    ) -> Result<String, String> {
+        let wasm = general_purpose::STANDARD
+            .decode(module.as_bytes())
+            .map_err(|_| format!("Invalid {}. Error decoding base64.", name.to_lowercase()))?
+            .into_boxed_slice();
+
+        let request = run::RunRequest {
+            wasm,
+            budget,
+            mem,
+            input,
+            metering: true,
+            timeout: None,
+            random_mode: RandomMode::Deterministic { seed },
+            clock_mode: ClockMode::Deterministic {
+                start: DEFAULT_CLOCK_START,
+                increment: DEFAULT_CLOCK_INCREMENT,
+            },
+            compiler: config().compiler,
+            canonicalize_nans: config().canonicalize_nans,
+            strict: config().strict,
+        };
+        let result = run_helper(name, request).await?;
+
+        String::from_utf8(result.stdout)
+            .map_err(|e| format!("{} wrote invalid UTF-8 to stdout: {}", name, e))
+    }
+}
+
+#[async_trait]
+impl Judger for GeneratorJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        check_limits(self.generator_cost, self.generator_memory)?;
+        check_limits(self.checker_cost, self.checker_memory)?;
+
+        let decodes = |module: &str| general_purpose::STANDARD.decode(module.as_bytes()).is_ok();
+        if !decodes(&self.generator) {
+            return Err("Invalid generator. Error decoding base64.".to_string());
+        }
+
+        match (&self.checker, &self.reference) {
+            (Some(checker), None) if !decodes(checker) => {
+                Err("Invalid checker. Error decoding base64.".to_string())
+            }
+            (None, Some(reference)) if !decodes(reference) => {
+                Err("Invalid reference. Error decoding base64.".to_string())
+            }
+            (Some(_), None) | (None, Some(_)) => Ok(()),
+            _ => Err("Must provide either checker or reference".to_string()),
+        }
+    }
+
+    /// Run the generator with the seed on its stdin, and use its output as the input.
+    async fn make_input(&self) -> Result<Input, String> {
+        let stdin = Self::run_seeded(
+            "Generator",
+            &self.generator,
+            self.generator_cost,
+            self.generator_memory,
+            format!("{}\n", self.seed),
+            self.seed,
+        )
+        .await?;
+
+        Ok(Input { stdin })
+    }
+
+    /// Judge the output with the checker like the `Checker` judger, or compare it token by token
+    /// with the output of the reference solution on the same input like the `Token` judger.
+    async fn judge_output(&self, input: &Input, output: &Output) -> Result<(), String> {
+        if let Some(checker) = &self.checker {
+            return run_checker(
+                checker,
+                self.checker_cost,
+                self.checker_memory,
+                input,
+                output,
+            )
+            .await;
+        }
+
+        let reference = self
+            .reference
+            .as_ref()
+            .ok_or_else(|| "Must provide either checker or reference".to_string())?;
+        let expected_output = Self::run_seeded(
+            "Reference",
+            reference,
+            self.checker_cost,
+            self.checker_memory,
+            input.stdin.clone(),
+            self.seed,
+        )
+        .await?;
+
+        compare_tokens(&expected_output, &output.stdout_text(), false)
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
@@ -0,0 +1,137 @@
+use super::{check_limits, Input, Judger, Output, SpecCommon, SpecInput};
+use crate::config::config;
+use crate::run::{self, CompiledModule, RunError, RunResult};
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InteractiveJudgeSpec {
+    /// The input, given to the checker only
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The base64-encoded WebAssembly binary of the checker interacting with the program
+    pub checker: String,
+    /// The maximum cost of the checker
+    pub checker_cost: u64,
+    /// The maximum memory of the checker
+    pub checker_memory: u32,
+    /// The limits and the scoring of the spec, the time limit covering the whole interaction
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+impl InteractiveJudgeSpec {
+    /// What the judge needs to run the program against the checker, owned so it can be moved to
+    /// the thread running the program.
+    pub fn interaction(&self) -> Interaction {
+        Interaction {
+            checker: self.checker.clone(),
+            checker_cost: self.checker_cost,
+            checker_memory: self.checker_memory,
+            // A solution and a checker waiting for each other would otherwise hang forever
+            timeout: self
+                .time_limit()
+                .unwrap_or_else(|| Duration::from_millis(config().helper_time_limit)),
+        }
+    }
+}
+
+/// The checker of an interactive spec, and the limits of the interaction.
+#[derive(Debug)]
+pub struct Interaction {
+    checker: String,
+    checker_cost: u64,
+    checker_memory: u32,
+    timeout: Duration,
+}
+
+impl Interaction {
+    /// Run the compiled program against the checker with the given input.
+    /// The stdout of the program goes to the checker, so the result carries the verdict of the
+    /// checker instead, its stderr if it exits successfully, or the reason it failed otherwise,
+    /// for `judge_output` to judge. The permit is held until both of them stop running, as in
+    /// `run::run_interactive`.
+    pub fn run<P: Send + Sync + 'static>(
+        self,
+        compiled: Arc<CompiledModule>,
+        input: &str,
+        permit: P,
+    ) -> Result<RunResult, RunError> {
+        let wasm = general_purpose::STANDARD
+            .decode(self.checker.as_bytes())
+            .map_err(|_| RunError::CompileError("Invalid checker".to_string()))?;
+        let checker = run::compile(
+            &wasm,
+            self.checker_cost,
+            self.checker_memory,
+            config().compile_options(),
+        )
+        .map_err(|e| RunError::CompileError(format!("Checker failed to compile: {}", e)))?;
+
+        let (result, checker_result) =
+            run::run_interactive(compiled, Arc::new(checker), input, self.timeout, permit);
+
+        let mut result = result?;
+        result.verdict = Some(match checker_result {
+            Ok(checker_result) if checker_result.exit_code != 0 => {
+                format!("Checker exited with code {}", checker_result.exit_code)
+            }
+            Ok(checker_result) => String::from_utf8_lossy(&checker_result.stderr).into_owned(),
+            Err(e) => format!("Checker failed: {}", e),
+        });
+        Ok(result)
+    }
+}
+
+#[async_trait]
+impl Judger for InteractiveJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        check_limits(self.checker_cost, self.checker_memory)?;
+        self.input.check()?;
+
+        if general_purpose::STANDARD
+            .decode(self.checker.as_bytes())
+            .is_err()
+        {
+            return Err("Invalid checker. Error decoding base64.".to_string());
+        }
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    /// Judge the verdict of the checker, set by `Interaction::run`.
+    /// The output is accepted if the checker printed nothing or a first line of `AC`, anything
+    /// else is reported as the reason of the rejection.
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        let verdict = output
+            .verdict
+            .as_deref()
+            .ok_or_else(|| "Checker gave no verdict".to_string())?
+            .trim();
+        if verdict.is_empty() || verdict.lines().next() == Some("AC") {
+            return Ok(());
+        }
+
+        Err(verdict.to_string())
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
@@ -1,137 +1,194 @@
-use super::{Input, Judger, Output};
+use super::{
+    check_hash_algorithm, diff_outputs, fetch_text, hash_digest, Input, Judger, Normalization,
+    Output, SpecCommon, SpecInput,
+};
 use async_trait::async_trait;
-use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, MokaManager};
-use reqwest::Client;
-use reqwest_middleware::ClientBuilder;
+use rocket::tokio::fs;
 use serde::{Deserialize, Serialize};
-use sha256::digest;
-use std::env;
+use std::path::Path;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FastIOJudgeSpec {
-    /// The input string
-    pub input: Option<String>,
-    /// The URL to fetch the input from
-    pub input_url: Option<String>,
-    /// The token used to authenticate the input URL
-    pub input_auth: Option<String>,
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The path to read the input from, only for specs judged by the CLI
+    pub input_file: Option<String>,
+    /// The expected hash of the input, checked after it is fetched and decompressed, computed
+    /// with the same algorithm as the output hash
+    pub input_hash: Option<String>,
     /// The expected output hash
-    pub output_hash: String,
-    /// The maximum cost of the program
-    pub cost: u64,
-    /// The maximum memory of the program
-    pub memory: u32,
+    pub output_hash: Option<String>,
+    /// The algorithm of the output hash, one of sha256 (default), sha512 or blake3
+    pub hash_algo: Option<String>,
+    /// The expected output, compared directly instead of by hash
+    pub expected_output: Option<String>,
+    /// Whether to compare the expected output ignoring ASCII case
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// The URL to fetch the expected output from
+    pub output_url: Option<String>,
+    /// The token used to authenticate the output URL
+    pub output_auth: Option<String>,
+    /// The expected stderr, compared like the expected output
+    pub judge_stderr: Option<String>,
+    /// The expected stderr hash, computed with the same algorithm as the output hash
+    pub stderr_hash: Option<String>,
+    /// Whether the stderr must be empty
+    #[serde(default)]
+    pub empty_stderr: bool,
+    /// How the output and stderr are normalized before they are compared
+    #[serde(flatten)]
+    pub normalization: Normalization,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
 }
 
 #[async_trait]
 impl Judger for FastIOJudgeSpec {
     async fn check_spec(&self) -> Result<(), String> {
-        if self.cost > 1000000000 {
-            return Err(format!(
-                "Invalid cost limit, got {}, max is 1,000,000,000",
-                self.cost
-            ));
+        self.common.check()?;
+        match &self.input_file {
+            Some(input_file) if Path::new(input_file).is_file() => {}
+            Some(input_file) => return Err(format!("Input file {} does not exist", input_file)),
+            None => self.input.check()?,
         }
+        check_hash_algorithm(&self.hash_algo)?;
 
-        if self.memory > 2048 {
-            return Err(format!(
-                "Invalid memory limit, got {}, max is 2,048",
-                self.memory
-            ));
+        if self.empty_stderr && (self.judge_stderr.is_some() || self.stderr_hash.is_some()) {
+            return Err(
+                "Cannot require an empty stderr along with judge_stderr or stderr_hash".to_string(),
+            );
         }
 
-        if self.input.is_none() && self.input_url.is_none() {
-            return Err("Must provide either input or input_url".to_string());
+        if self.output_hash.is_none() && self.expected_output.is_none() && self.output_url.is_none()
+        {
+            return Err(
+                "Must provide either output_hash, expected_output or output_url".to_string(),
+            );
         }
 
         Ok(())
     }
 
     async fn make_input(&self) -> Result<Input, String> {
-        if let Some(input) = &self.input {
-            return Ok(Input {
-                stdin: input.clone(),
-            });
+        let input = match &self.input_file {
+            Some(input_file) => Input {
+                stdin: fs::read_to_string(input_file)
+                    .await
+                    .map_err(|e| format!("Error reading input file {}: {}", input_file, e))?,
+            },
+            None => self.input.load().await?,
+        };
+
+        if let Some(input_hash) = &self.input_hash {
+            let actual = hash_digest(&self.hash_algo, input.stdin.as_bytes());
+            if !actual.eq_ignore_ascii_case(input_hash) {
+                return Err(format!(
+                    "Input hash mismatch, expected {}, got {}",
+                    input_hash, actual
+                ));
+            }
         }
 
-        if let Some(input_url) = &self.input_url {
-            let mut client = ClientBuilder::new(Client::new());
-
-            match env::var("REMOTE_INPUT_CACHE") {
-                Ok(val) => match val.as_str() {
-                    "true" | "1" | "cacache" => {
-                        client = client.with(Cache(HttpCache {
-                            mode: CacheMode::Default,
-                            manager: CACacheManager::default(),
-                            options: None,
-                        }));
-                    }
-                    "mem" | "moka" => {
-                        client = client.with(Cache(HttpCache {
-                            mode: CacheMode::Default,
-                            manager: MokaManager::default(),
-                            options: None,
-                        }));
-                    }
-                    _ => {}
-                },
-                Err(_) => {
-                    client = client.with(Cache(HttpCache {
-                        mode: CacheMode::Default,
-                        manager: MokaManager::default(),
-                        options: None,
-                    }));
-                }
+        Ok(input)
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        // Binary output can not be normalized, so only a hash-only spec gets here with it, and
+        // hashes the raw bytes
+        let stdout = match std::str::from_utf8(&output.stdout) {
+            Ok(stdout) => self.normalization.normalize(stdout).into_bytes(),
+            Err(_) => output.stdout.clone(),
+        };
+
+        if let Some(expected_output) = &self.expected_output {
+            let mut expected_output = self.normalization.normalize(expected_output);
+            let mut stdout = String::from_utf8_lossy(&stdout).into_owned();
+            if self.case_insensitive {
+                expected_output.make_ascii_lowercase();
+                stdout.make_ascii_lowercase();
             }
+            if stdout != expected_output {
+                return Err(diff_outputs("Output", &expected_output, &stdout));
+            }
+        }
 
-            let client = client.build();
+        if let Some(output_url) = &self.output_url {
+            let expected_output = self
+                .normalization
+                .normalize(&fetch_text("output", output_url, &self.output_auth, &None).await?);
+            if hash_digest(&self.hash_algo, expected_output.as_bytes())
+                != hash_digest(&self.hash_algo, &stdout)
+            {
+                return Err(diff_outputs(
+                    "Output",
+                    &expected_output,
+                    &String::from_utf8_lossy(&stdout),
+                ));
+            }
+        }
 
-            let mut req = client.get(input_url);
-            if let Some(auth) = &self.input_auth {
-                req = req.header("Authorization", format!("Bearer {}", auth));
+        if let Some(expected_hash) = &self.output_hash {
+            let output_hash = hash_digest(&self.hash_algo, &stdout);
+            if !output_hash.eq_ignore_ascii_case(expected_hash) {
+                return Err(format!(
+                    "Output hash mismatch. Expected {}, got {}",
+                    expected_hash, output_hash
+                ));
             }
+        }
 
-            info!("Fetching input from {}", input_url);
-            let res = req
-                .send()
-                .await
-                .map_err(|e| format!("Error fetching input: {}", e))?;
+        self.judge_stderr(&output.stderr)
+    }
 
-            let input = res
-                .text()
-                .await
-                .map_err(|e| format!("Error reading input: {}", e))?;
-            info!("Fetched input from {}", input_url);
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
 
-            return Ok(Input { stdin: input });
-        }
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
 
-        unreachable!()
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
     }
 
-    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
-        let output_hash = digest(
-            output
-                .stdout
-                .lines()
-                .map(|l| l.trim_end())
-                .collect::<Vec<_>>()
-                .join("\n")
-                .trim()
-                .as_bytes(),
-        );
-
-        if output_hash != self.output_hash {
-            return Err(format!(
-                "Output hash mismatch. Expected {}, got {}",
-                self.output_hash, output_hash
-            ));
+    /// Only the hash of the output is compared when there is no expected output, so it may be
+    /// binary.
+    fn binary_output(&self) -> bool {
+        self.expected_output.is_none() && self.output_url.is_none()
+    }
+}
+
+impl FastIOJudgeSpec {
+    /// Judge the stderr of the program against the expected stderr, its hash, or emptiness.
+    fn judge_stderr(&self, stderr: &str) -> Result<(), String> {
+        let stderr = self.normalization.normalize(stderr);
+
+        if self.empty_stderr && !stderr.is_empty() {
+            return Err(format!("Stderr is not empty, got {} bytes", stderr.len()));
         }
 
-        Ok(())
-    }
+        if let Some(judge_stderr) = &self.judge_stderr {
+            let expected_stderr = self.normalization.normalize(judge_stderr);
+            if stderr != expected_stderr {
+                return Err(diff_outputs("Stderr", &expected_stderr, &stderr));
+            }
+        }
 
-    fn limits(&self) -> (u64, u32) {
-        (self.cost, self.memory)
+        if let Some(expected_hash) = &self.stderr_hash {
+            let stderr_hash = hash_digest(&self.hash_algo, stderr.as_bytes());
+            if !stderr_hash.eq_ignore_ascii_case(expected_hash) {
+                return Err(format!(
+                    "Stderr hash mismatch. Expected {}, got {}",
+                    expected_hash, stderr_hash
+                ));
+            }
+        }
+
+        Ok(())
     }
 }
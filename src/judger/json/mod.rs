@@ -0,0 +1,156 @@
+use super::{Input, Judger, Output, SpecCommon, SpecInput, DIFF_MAX_WIDTH};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JsonJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The expected output, a JSON document compared by value
+    pub expected_output: String,
+    /// The absolute tolerance of numbers, exact by default
+    #[serde(default)]
+    pub eps_abs: f64,
+    /// The relative tolerance of numbers, relative to the expected value, exact by default
+    #[serde(default)]
+    pub eps_rel: f64,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+#[async_trait]
+impl Judger for JsonJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        self.input.check()?;
+
+        if !(self.eps_abs >= 0.0 && self.eps_rel >= 0.0) {
+            return Err(format!(
+                "Invalid tolerance, got eps_abs {} and eps_rel {}, both must be non-negative",
+                self.eps_abs, self.eps_rel
+            ));
+        }
+
+        serde_json::from_str::<Value>(&self.expected_output)
+            .map_err(|e| format!("Invalid expected output, not valid JSON: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        let expected: Value = serde_json::from_str(&self.expected_output)
+            .map_err(|e| format!("Invalid expected output, not valid JSON: {}", e))?;
+        let actual: Value = serde_json::from_str(&output.stdout_text())
+            .map_err(|e| format!("Output is not valid JSON: {}", e))?;
+
+        self.compare("", &expected, &actual)
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+impl JsonJudgeSpec {
+    /// Compare two values at the JSON pointer `path`, objects regardless of the order of their
+    /// keys, and numbers within the tolerance. The first mismatch is reported with its path.
+    fn compare(&self, path: &str, expected: &Value, actual: &Value) -> Result<(), String> {
+        match (expected, actual) {
+            (Value::Object(e), Value::Object(a)) => {
+                if let Some(key) = e.keys().find(|key| !a.contains_key(*key)) {
+                    return Err(format!("Missing key at {}", location(&child(path, key))));
+                }
+                if let Some(key) = a.keys().find(|key| !e.contains_key(*key)) {
+                    return Err(format!("Unexpected key at {}", location(&child(path, key))));
+                }
+                for (key, value) in e {
+                    self.compare(&child(path, key), value, &a[key])?;
+                }
+                Ok(())
+            }
+            (Value::Array(e), Value::Array(a)) => {
+                for (index, (e, a)) in e.iter().zip(a).enumerate() {
+                    self.compare(&child(path, &index.to_string()), e, a)?;
+                }
+                if e.len() != a.len() {
+                    return Err(format!(
+                        "Array length mismatch at {}. Expected {}, got {}",
+                        location(path),
+                        e.len(),
+                        a.len()
+                    ));
+                }
+                Ok(())
+            }
+            (Value::Number(e), Value::Number(a)) if self.numbers_match(e, a) => Ok(()),
+            _ if expected == actual => Ok(()),
+            _ => Err(format!(
+                "Value mismatch at {}. Expected `{}`, got `{}`",
+                location(path),
+                shorten(expected),
+                shorten(actual)
+            )),
+        }
+    }
+
+    /// Whether two numbers match, exactly if both are integers and there is no tolerance, within
+    /// the tolerance otherwise.
+    fn numbers_match(&self, expected: &Number, actual: &Number) -> bool {
+        if expected == actual {
+            return true;
+        }
+
+        // Integers beyond 2^53 are not exact as floats, so compare them as integers
+        let exact = self.eps_abs == 0.0 && self.eps_rel == 0.0;
+        if exact && !expected.is_f64() && !actual.is_f64() {
+            return false;
+        }
+
+        match (expected.as_f64(), actual.as_f64()) {
+            (Some(e), Some(a)) => (a - e).abs() <= self.eps_abs.max(self.eps_rel * e.abs()),
+            _ => false,
+        }
+    }
+}
+
+/// The JSON pointer of the member `key` of the value at `path`.
+fn child(path: &str, key: &str) -> String {
+    format!("{}/{}", path, key.replace('~', "~0").replace('/', "~1"))
+}
+
+/// Describe a JSON pointer, which is empty for the whole document.
+fn location(path: &str) -> String {
+    match path {
+        "" => "the root".to_string(),
+        _ => format!("`{}`", path),
+    }
+}
+
+/// A compact JSON value, cut to the width of a diff line.
+fn shorten(value: &Value) -> String {
+    let value = value.to_string();
+    if value.chars().count() > DIFF_MAX_WIDTH {
+        format!(
+            "{}...",
+            value.chars().take(DIFF_MAX_WIDTH).collect::<String>()
+        )
+    } else {
+        value
+    }
+}
@@ -0,0 +1,136 @@
+use super::{Input, Judger, Normalization, Output, SpecCommon, SpecInput, DIFF_MAX_WIDTH};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The maximum number of missing or unexpected lines reported on a mismatch.
+const MAX_REPORTED_LINES: usize = 5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LinesJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The expected output, compared line by line in any order
+    pub expected_output: String,
+    /// Whether to compare lines ignoring ASCII case
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// How the output is normalized before it is split into lines
+    #[serde(flatten)]
+    pub normalization: Normalization,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+impl LinesJudgeSpec {
+    /// Count the lines of a normalized output, skipping blank lines.
+    fn count_lines(&self, output: &str) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for line in self.normalization.normalize(output).split('\n') {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line = if self.case_insensitive {
+                line.to_ascii_lowercase()
+            } else {
+                line.to_string()
+            };
+            *counts.entry(line).or_insert(0) += 1;
+        }
+        counts
+    }
+}
+
+#[async_trait]
+impl Judger for LinesJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        self.input.check()?;
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        let expected = self.count_lines(&self.expected_output);
+        let actual = self.count_lines(&output.stdout_text());
+        if expected == actual {
+            return Ok(());
+        }
+
+        let missing = difference(&expected, &actual);
+        let unexpected = difference(&actual, &expected);
+
+        let mut message = "Line set mismatch.".to_string();
+        if !missing.is_empty() {
+            message.push_str(&format!("\nMissing lines:\n{}", list_lines(&missing)));
+        }
+        if !unexpected.is_empty() {
+            message.push_str(&format!("\nUnexpected lines:\n{}", list_lines(&unexpected)));
+        }
+        Err(message)
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+/// The lines of `a` missing from `b`, with how many more times they appear in `a`.
+fn difference<'a>(
+    a: &'a BTreeMap<String, usize>,
+    b: &BTreeMap<String, usize>,
+) -> Vec<(&'a str, usize)> {
+    a.iter()
+        .filter_map(|(line, &count)| {
+            let other = b.get(line).copied().unwrap_or(0);
+            match count.checked_sub(other) {
+                Some(extra) if extra > 0 => Some((line.as_str(), extra)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// List the first lines of a difference, with their counts if they appear more than once.
+fn list_lines(lines: &[(&str, usize)]) -> String {
+    let mut listed: Vec<String> = lines
+        .iter()
+        .take(MAX_REPORTED_LINES)
+        .map(|&(line, count)| {
+            let line = if line.chars().count() > DIFF_MAX_WIDTH {
+                format!(
+                    "{}...",
+                    line.chars().take(DIFF_MAX_WIDTH).collect::<String>()
+                )
+            } else {
+                line.to_string()
+            };
+            match count {
+                1 => format!("  {}", line),
+                _ => format!("  {} (x{})", line, count),
+            }
+        })
+        .collect();
+    if lines.len() > MAX_REPORTED_LINES {
+        listed.push(format!(
+            "  ... and {} more",
+            lines.len() - MAX_REPORTED_LINES
+        ));
+    }
+    listed.join("\n")
+}
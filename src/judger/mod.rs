@@ -1,8 +1,37 @@
+use crate::config::{
+    config, fetch_attempts, fetch_retry_delay, fetch_timeout, input_cache_dir, max_input_size,
+};
+use crate::permit::acquire_run_permit;
+use crate::run::{self, RunRequest, RunResult};
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
 use async_trait::async_trait;
+use http_cache_reqwest::{CACacheManager, Cache, CacheMode, HttpCache, MokaManager};
+use reqwest::{header, Client, StatusCode};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use rocket::tokio::io::AsyncReadExt;
+use rocket::tokio::task;
+use rocket::tokio::time::sleep;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha512};
+use sha256::digest;
+use std::borrow::Cow;
+use std::env;
 use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
 
+pub mod cases;
+pub mod checker;
+pub mod float;
+pub mod generator;
+pub mod interactive;
 pub mod io_fast;
+pub mod json;
+pub mod lines;
+pub mod pattern;
+pub mod token;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Input {
@@ -11,14 +40,47 @@ pub struct Input {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Output {
-    pub stdout: String,
+    pub stdout: Vec<u8>,
     pub stderr: String,
+    /// The verdict of the checker of an interactive spec
+    pub verdict: Option<String>,
+}
+
+impl Output {
+    /// The stdout as text. Only the specs accepting binary output are judged on a stdout that is
+    /// not valid UTF-8, so nothing is replaced for the others.
+    pub fn stdout_text(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.stdout)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "judger")]
 pub enum JudgeSpec {
     IOFast(io_fast::FastIOJudgeSpec),
+    Token(token::TokenJudgeSpec),
+    Float(float::FloatJudgeSpec),
+    Checker(checker::CheckerJudgeSpec),
+    Regex(pattern::RegexJudgeSpec),
+    Lines(lines::LinesJudgeSpec),
+    Json(json::JsonJudgeSpec),
+    Generator(generator::GeneratorJudgeSpec),
+    Interactive(interactive::InteractiveJudgeSpec),
+    Cases(cases::CasesJudgeSpec),
+}
+
+impl JudgeSpec {
+    /// Whether the spec, or one of its cases, reads a file from the local disk, which only the
+    /// CLI allows, since the server must not let submissions read its files.
+    pub fn reads_local_files(&self) -> bool {
+        match self {
+            JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.input_file.is_some(),
+            JudgeSpec::Cases(cases_spec) => {
+                cases_spec.cases.iter().any(|case| case.reads_local_files())
+            }
+            _ => false,
+        }
+    }
 }
 
 #[async_trait]
@@ -27,6 +89,13 @@ pub trait Judger: Debug {
     async fn make_input(&self) -> Result<Input, String>;
     async fn judge_output(&self, input: &Input, output: &Output) -> Result<(), String>;
     fn limits(&self) -> (u64, u32);
+    fn time_limit(&self) -> Option<Duration>;
+    fn scoring(&self) -> (Option<String>, Option<f64>);
+    /// Whether the output is judged as raw bytes, so a stdout that is not valid UTF-8 is judged
+    /// like any other instead of failing the run.
+    fn binary_output(&self) -> bool {
+        false
+    }
 }
 
 #[async_trait]
@@ -34,24 +103,627 @@ impl Judger for JudgeSpec {
     async fn check_spec(&self) -> Result<(), String> {
         match self {
             JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.check_spec().await,
+            JudgeSpec::Token(token_spec) => token_spec.check_spec().await,
+            JudgeSpec::Float(float_spec) => float_spec.check_spec().await,
+            JudgeSpec::Checker(checker_spec) => checker_spec.check_spec().await,
+            JudgeSpec::Regex(regex_spec) => regex_spec.check_spec().await,
+            JudgeSpec::Lines(lines_spec) => lines_spec.check_spec().await,
+            JudgeSpec::Json(json_spec) => json_spec.check_spec().await,
+            JudgeSpec::Generator(generator_spec) => generator_spec.check_spec().await,
+            JudgeSpec::Interactive(interactive_spec) => interactive_spec.check_spec().await,
+            JudgeSpec::Cases(cases_spec) => cases_spec.check_spec().await,
         }
     }
 
     async fn make_input(&self) -> Result<Input, String> {
         match self {
             JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.make_input().await,
+            JudgeSpec::Token(token_spec) => token_spec.make_input().await,
+            JudgeSpec::Float(float_spec) => float_spec.make_input().await,
+            JudgeSpec::Checker(checker_spec) => checker_spec.make_input().await,
+            JudgeSpec::Regex(regex_spec) => regex_spec.make_input().await,
+            JudgeSpec::Lines(lines_spec) => lines_spec.make_input().await,
+            JudgeSpec::Json(json_spec) => json_spec.make_input().await,
+            JudgeSpec::Generator(generator_spec) => generator_spec.make_input().await,
+            JudgeSpec::Interactive(interactive_spec) => interactive_spec.make_input().await,
+            JudgeSpec::Cases(_) => Err("Cases are judged case by case".to_string()),
         }
     }
 
     async fn judge_output(&self, input: &Input, output: &Output) -> Result<(), String> {
         match self {
             JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.judge_output(input, output).await,
+            JudgeSpec::Token(token_spec) => token_spec.judge_output(input, output).await,
+            JudgeSpec::Float(float_spec) => float_spec.judge_output(input, output).await,
+            JudgeSpec::Checker(checker_spec) => checker_spec.judge_output(input, output).await,
+            JudgeSpec::Regex(regex_spec) => regex_spec.judge_output(input, output).await,
+            JudgeSpec::Lines(lines_spec) => lines_spec.judge_output(input, output).await,
+            JudgeSpec::Json(json_spec) => json_spec.judge_output(input, output).await,
+            JudgeSpec::Generator(generator_spec) => {
+                generator_spec.judge_output(input, output).await
+            }
+            JudgeSpec::Interactive(interactive_spec) => {
+                interactive_spec.judge_output(input, output).await
+            }
+            JudgeSpec::Cases(_) => Err("Cases are judged case by case".to_string()),
         }
     }
 
     fn limits(&self) -> (u64, u32) {
         match self {
             JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.limits(),
+            JudgeSpec::Token(token_spec) => token_spec.limits(),
+            JudgeSpec::Float(float_spec) => float_spec.limits(),
+            JudgeSpec::Checker(checker_spec) => checker_spec.limits(),
+            JudgeSpec::Regex(regex_spec) => regex_spec.limits(),
+            JudgeSpec::Lines(lines_spec) => lines_spec.limits(),
+            JudgeSpec::Json(json_spec) => json_spec.limits(),
+            JudgeSpec::Generator(generator_spec) => generator_spec.limits(),
+            JudgeSpec::Interactive(interactive_spec) => interactive_spec.limits(),
+            JudgeSpec::Cases(cases_spec) => cases_spec.limits(),
+        }
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        match self {
+            JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.time_limit(),
+            JudgeSpec::Token(token_spec) => token_spec.time_limit(),
+            JudgeSpec::Float(float_spec) => float_spec.time_limit(),
+            JudgeSpec::Checker(checker_spec) => checker_spec.time_limit(),
+            JudgeSpec::Regex(regex_spec) => regex_spec.time_limit(),
+            JudgeSpec::Lines(lines_spec) => lines_spec.time_limit(),
+            JudgeSpec::Json(json_spec) => json_spec.time_limit(),
+            JudgeSpec::Generator(generator_spec) => generator_spec.time_limit(),
+            JudgeSpec::Interactive(interactive_spec) => interactive_spec.time_limit(),
+            JudgeSpec::Cases(cases_spec) => cases_spec.time_limit(),
+        }
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        match self {
+            JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.scoring(),
+            JudgeSpec::Token(token_spec) => token_spec.scoring(),
+            JudgeSpec::Float(float_spec) => float_spec.scoring(),
+            JudgeSpec::Checker(checker_spec) => checker_spec.scoring(),
+            JudgeSpec::Regex(regex_spec) => regex_spec.scoring(),
+            JudgeSpec::Lines(lines_spec) => lines_spec.scoring(),
+            JudgeSpec::Json(json_spec) => json_spec.scoring(),
+            JudgeSpec::Generator(generator_spec) => generator_spec.scoring(),
+            JudgeSpec::Interactive(interactive_spec) => interactive_spec.scoring(),
+            JudgeSpec::Cases(cases_spec) => cases_spec.scoring(),
+        }
+    }
+
+    fn binary_output(&self) -> bool {
+        match self {
+            JudgeSpec::IOFast(io_fast_spec) => io_fast_spec.binary_output(),
+            _ => false,
+        }
+    }
+}
+
+/// Load a judge spec from a file, in YAML if its extension is `.yaml` or `.yml`, or in JSON
+/// otherwise. YAML block scalars make long inputs and expected outputs easier to write by hand.
+pub fn load_spec(path: &Path) -> Result<JudgeSpec, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read judge spec: {}", e))?;
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("yaml") | Some("yml") => {
+            serde_yaml::from_str(&content).map_err(|e| format!("Invalid judge spec: {}", e))
+        }
+        _ => serde_json::from_str(&content).map_err(|e| format!("Invalid judge spec: {}", e)),
+    }
+}
+
+/// Check the cost and memory limits of a judge spec against the configured maxima, the same
+/// ones `run::run` enforces.
+pub fn check_limits(cost: u64, memory: u32) -> Result<(), String> {
+    run::check_limits(cost, memory, config().max_cost, config().max_memory)
+}
+
+/// Check the wall-clock time limit of a judge spec, in milliseconds.
+pub fn check_time_limit(time_limit: Option<u64>) -> Result<(), String> {
+    if let Some(time_limit) = time_limit {
+        if time_limit == 0 || time_limit > 60000 {
+            return Err(format!(
+                "Invalid time limit, got {}, must be between 1 and 60,000",
+                time_limit
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The input of a judge spec, given inline or fetched from a URL.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SpecInput {
+    /// The input string
+    pub input: Option<String>,
+    /// The URL to fetch the input from
+    pub input_url: Option<String>,
+    /// The token used to authenticate the input URL
+    pub input_auth: Option<String>,
+    /// The compression of the input URL, gzip or zstd, defaults to its Content-Encoding
+    pub input_compression: Option<String>,
+}
+
+impl SpecInput {
+    /// Check that the input is given either inline or by URL, with a supported compression.
+    pub fn check(&self) -> Result<(), String> {
+        if self.input.is_none() && self.input_url.is_none() {
+            return Err("Must provide either input or input_url".to_string());
+        }
+
+        match self.input_compression.as_deref() {
+            None | Some("gzip") | Some("zstd") => Ok(()),
+            Some(compression) => Err(format!(
+                "Unknown input compression {}, supported compressions are gzip and zstd",
+                compression
+            )),
+        }
+    }
+
+    /// Load the input, either inline or fetched from the URL.
+    pub async fn load(&self) -> Result<Input, String> {
+        if let Some(input) = &self.input {
+            return Ok(Input {
+                stdin: input.clone(),
+            });
         }
+
+        if let Some(input_url) = &self.input_url {
+            let input = fetch_text(
+                "input",
+                input_url,
+                &self.input_auth,
+                &self.input_compression,
+            )
+            .await?;
+            return Ok(Input { stdin: input });
+        }
+
+        unreachable!()
     }
 }
+
+/// The limits and the scoring of a judge spec.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SpecCommon {
+    /// The subtask of the spec, all specs of a subtask must pass to earn its points
+    pub subtask: Option<String>,
+    /// The points earned by passing the spec
+    pub points: Option<f64>,
+    /// The maximum cost of the program
+    pub cost: u64,
+    /// The maximum memory of the program
+    pub memory: u32,
+    /// The wall-clock time limit of the program, in milliseconds
+    pub time_limit: Option<u64>,
+}
+
+impl SpecCommon {
+    /// Check the limits against the configured maxima.
+    pub fn check(&self) -> Result<(), String> {
+        check_limits(self.cost, self.memory)?;
+        check_time_limit(self.time_limit)
+    }
+
+    pub fn limits(&self) -> (u64, u32) {
+        (self.cost, self.memory)
+    }
+
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.time_limit.map(Duration::from_millis)
+    }
+
+    pub fn scoring(&self) -> (Option<String>, Option<f64>) {
+        (self.subtask.clone(), self.points)
+    }
+}
+
+/// Run a helper module of a judge spec, such as a checker or a generator, named `name` in the
+/// errors. Like the program, it waits for a run permit, and it is given up on after
+/// `HELPER_TIME_LIMIT` milliseconds. A helper that fails or exits with a nonzero code is an error.
+pub async fn run_helper(name: &str, mut request: RunRequest) -> Result<RunResult, String> {
+    request.timeout = Some(Duration::from_millis(config().helper_time_limit));
+
+    let permit = acquire_run_permit().await;
+    let result = task::spawn_blocking(move || run::run_with_permit(request, permit))
+        .await
+        .map_err(|e| format!("{} panicked: {}", name, e))?
+        .map_err(|e| format!("{} failed: {}", name, e))?;
+
+    if result.exit_code != 0 {
+        return Err(format!("{} exited with code {}", name, result.exit_code));
+    }
+
+    Ok(result)
+}
+
+/// The HTTP client shared by all judge specs, so their requests reuse pooled connections
+/// and the same cache.
+static HTTP_CLIENT: OnceLock<ClientWithMiddleware> = OnceLock::new();
+
+/// Get the shared HTTP client, building it on first use.
+/// Responses are cached as configured by the `REMOTE_INPUT_CACHE` environment variable, on disk
+/// in `INPUT_CACHE_DIR`, in memory, or not at all.
+fn http_client() -> &'static ClientWithMiddleware {
+    HTTP_CLIENT.get_or_init(|| {
+        // The timeout covers the whole fetch, so a server stalling in the middle of a body can
+        // not hang the judge either
+        let inner = Client::builder()
+            .timeout(Duration::from_secs(fetch_timeout()))
+            .build()
+            .expect("Failed to build the HTTP client");
+        let client = ClientBuilder::new(inner);
+
+        let cache_dir = input_cache_dir();
+        let mode = env::var("REMOTE_INPUT_CACHE").unwrap_or_else(|_| {
+            if cache_dir.is_some() {
+                "cacache".to_owned()
+            } else {
+                "moka".to_owned()
+            }
+        });
+
+        let client = match mode.as_str() {
+            "true" | "1" | "cacache" => client.with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: match cache_dir {
+                    Some(path) => CACacheManager { path },
+                    None => CACacheManager::default(),
+                },
+                options: None,
+            })),
+            "mem" | "moka" => client.with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: MokaManager::default(),
+                options: None,
+            })),
+            // Never write the responses anywhere, for read-only or ephemeral filesystems
+            _ => client.with(Cache(HttpCache {
+                mode: CacheMode::NoStore,
+                manager: MokaManager::default(),
+                options: None,
+            })),
+        };
+
+        client.build()
+    })
+}
+
+/// Fetch a text file of a judge spec, such as its input, through the cached HTTP client.
+/// The body is decompressed with the given compression, or the Content-Encoding of the response.
+/// Both the fetched and the decompressed body are limited to `MAX_INPUT_SIZE` bytes.
+pub async fn fetch_text(
+    name: &str,
+    url: &str,
+    auth: &Option<String>,
+    compression: &Option<String>,
+) -> Result<String, String> {
+    let max_size = max_input_size();
+    let (body, encoding) = fetch(name, url, auth, max_size).await?;
+    let encoding = compression.clone().or(encoding);
+
+    let body = decompress(name, encoding.as_deref(), &body, max_size).await?;
+    String::from_utf8(body).map_err(|e| format!("Error reading {}: {}", name, e))
+}
+
+/// Fetch a binary file through the cached HTTP client, such as a module, failing if the response
+/// is not successful or the body is larger than `max_size` bytes.
+pub async fn fetch_bytes(
+    name: &str,
+    url: &str,
+    auth: &Option<String>,
+    max_size: u64,
+) -> Result<Vec<u8>, String> {
+    fetch(name, url, auth, max_size).await.map(|(body, _)| body)
+}
+
+/// Fetch a file through the cached HTTP client, with its Content-Encoding, if any.
+/// Network errors, server errors and `429 Too Many Requests` are retried up to `FETCH_ATTEMPTS`
+/// attempts in total, with an exponential backoff from `FETCH_RETRY_DELAY`, while other errors,
+/// such as `404 Not Found`, fail right away. Cached responses are returned by the first attempt.
+async fn fetch(
+    name: &str,
+    url: &str,
+    auth: &Option<String>,
+    max_size: u64,
+) -> Result<(Vec<u8>, Option<String>), String> {
+    let attempts = fetch_attempts();
+    let mut delay = fetch_retry_delay();
+
+    for attempt in 1..=attempts {
+        let error = match fetch_once(name, url, auth, max_size).await {
+            Ok(fetched) => return Ok(fetched),
+            Err(FetchError::Permanent(error)) => return Err(error),
+            Err(FetchError::Transient(error)) if attempt == attempts => return Err(error),
+            Err(FetchError::Transient(error)) => error,
+        };
+
+        info!("{}, retrying in {} ms ({})", error, delay, url);
+        sleep(Duration::from_millis(delay)).await;
+        delay = delay.saturating_mul(2);
+    }
+
+    unreachable!("There is always at least one fetch attempt")
+}
+
+/// Why a fetch failed, and whether it is worth retrying.
+enum FetchError {
+    /// A network error or a server error, which may succeed on the next attempt.
+    Transient(String),
+    /// An error that will not go away by retrying, like a client error or a body too large.
+    Permanent(String),
+}
+
+/// Fetch a file once.
+/// The body is read chunk by chunk, and the fetch fails as soon as it is larger than `max_size`
+/// bytes, so a huge or endless response can not exhaust the memory of the server.
+async fn fetch_once(
+    name: &str,
+    url: &str,
+    auth: &Option<String>,
+    max_size: u64,
+) -> Result<(Vec<u8>, Option<String>), FetchError> {
+    let client = http_client();
+
+    let mut req = client.get(url);
+    if let Some(auth) = auth {
+        req = req.header("Authorization", format!("Bearer {}", auth));
+    }
+
+    info!("Fetching {} from {}", name, url);
+    let mut res = req
+        .send()
+        .await
+        .map_err(|e| FetchError::Transient(format!("Error fetching {}: {}", name, e)))?;
+    let status = res.status();
+    if !status.is_success() {
+        let error = format!("Error fetching {}: {}", name, status);
+        return Err(
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                FetchError::Transient(error)
+            } else {
+                FetchError::Permanent(error)
+            },
+        );
+    }
+
+    let encoding = res
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|encoding| encoding.to_str().ok())
+        .map(|encoding| encoding.trim().to_lowercase());
+
+    let too_large = || {
+        FetchError::Permanent(format!(
+            "Error fetching {}: larger than {} bytes",
+            name, max_size
+        ))
+    };
+    if res
+        .content_length()
+        .map_or(false, |length| length > max_size)
+    {
+        return Err(too_large());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = res
+        .chunk()
+        .await
+        .map_err(|e| FetchError::Transient(format!("Error reading {}: {}", name, e)))?
+    {
+        if (body.len() + chunk.len()) as u64 > max_size {
+            return Err(too_large());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    info!("Fetched {} from {}", name, url);
+
+    Ok((body, encoding))
+}
+
+/// Decompress a fetched body, failing if it decompresses to more than `max_size` bytes so a
+/// small compressed file can not exhaust the memory of the server.
+async fn decompress(
+    name: &str,
+    encoding: Option<&str>,
+    body: &[u8],
+    max_size: u64,
+) -> Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    let limit = max_size + 1;
+
+    let result = match encoding {
+        None | Some("identity") => return Ok(body.to_vec()),
+        Some("gzip") => {
+            GzipDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decompressed)
+                .await
+        }
+        Some("zstd") => {
+            ZstdDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decompressed)
+                .await
+        }
+        Some(encoding) => {
+            return Err(format!(
+                "Error decompressing {}: unsupported encoding {}",
+                name, encoding
+            ))
+        }
+    };
+    result.map_err(|e| format!("Error decompressing {}: {}", name, e))?;
+
+    if decompressed.len() as u64 > max_size {
+        return Err(format!(
+            "Error decompressing {}: larger than {} bytes",
+            name, max_size
+        ));
+    }
+
+    Ok(decompressed)
+}
+
+/// Check that the hash algorithm of a judge spec is supported.
+pub fn check_hash_algorithm(algorithm: &Option<String>) -> Result<(), String> {
+    match algorithm.as_deref() {
+        None | Some("sha256") | Some("sha512") | Some("blake3") => Ok(()),
+        Some(algorithm) => Err(format!(
+            "Unknown hash algorithm {}, supported algorithms are sha256, sha512 and blake3",
+            algorithm
+        )),
+    }
+}
+
+/// Compute the lowercase hex digest of the data with the hash algorithm of a judge spec,
+/// which defaults to sha256. The algorithm must have been checked by `check_hash_algorithm`.
+pub fn hash_digest(algorithm: &Option<String>, data: &[u8]) -> String {
+    match algorithm.as_deref() {
+        None | Some("sha256") => digest(data),
+        Some("sha512") => format!("{:x}", Sha512::digest(data)),
+        Some("blake3") => blake3::hash(data).to_hex().to_string(),
+        Some(algorithm) => unreachable!("Unknown hash algorithm {}", algorithm),
+    }
+}
+
+/// How an output is normalized before it is compared, all steps are enabled by default.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalization {
+    /// Trim the trailing whitespace of each line, only spaces and tabs if CRLF line endings are
+    /// not normalized
+    pub trim_lines: bool,
+    /// Trim the whitespace around the whole output
+    pub trim_output: bool,
+    /// Replace CRLF line endings with LF
+    pub normalize_crlf: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Normalization {
+            trim_lines: true,
+            trim_output: true,
+            normalize_crlf: true,
+        }
+    }
+}
+
+impl Normalization {
+    /// Normalize the output, replacing CRLF line endings first, then trimming each line, then
+    /// trimming the whole output.
+    pub fn normalize(&self, output: &str) -> String {
+        let mut output = if self.normalize_crlf {
+            output.replace("\r\n", "\n")
+        } else {
+            output.to_string()
+        };
+
+        if self.trim_lines {
+            // A trailing `\r` is kept unless CRLF line endings are normalized
+            let crlf = self.normalize_crlf;
+            output = output
+                .split('\n')
+                .map(|l| {
+                    if crlf {
+                        l.trim_end()
+                    } else {
+                        l.trim_end_matches([' ', '\t'])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        if self.trim_output {
+            output = output.trim().to_string();
+        }
+
+        output
+    }
+}
+
+/// The number of lines shown before the first differing line of a diff.
+const DIFF_CONTEXT_LINES: usize = 2;
+/// The number of lines shown from the first differing line of a diff.
+const DIFF_MAX_LINES: usize = 10;
+/// The maximum number of characters shown for each line of a diff.
+const DIFF_MAX_WIDTH: usize = 200;
+
+/// Describe the difference of two normalized outputs, named like `Output` or `Stderr`, as a
+/// unified-diff-style snippet, comparing them line by line. The first differing line is reported
+/// with its line number in the normalized output, then the snippet starts a few lines before it
+/// and is capped in both lines and width, with a count of the differing lines left out.
+pub fn diff_outputs(name: &str, expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    let total = expected.len().max(actual.len());
+    let differs = |i: usize| expected.get(i) != actual.get(i);
+
+    let first = match (0..total).find(|&i| differs(i)) {
+        Some(first) => first,
+        None => return format!("{} mismatch.", name),
+    };
+    let truncate = |line: &str| {
+        if line.chars().count() > DIFF_MAX_WIDTH {
+            format!(
+                "{}...",
+                line.chars().take(DIFF_MAX_WIDTH).collect::<String>()
+            )
+        } else {
+            line.to_string()
+        }
+    };
+
+    let describe = |line: Option<&&str>| match line {
+        Some(line) => format!("`{}`", truncate(line)),
+        None => "end of output".to_string(),
+    };
+
+    let mut diff = vec![format!(
+        "{} mismatch at line {}: expected {}, got {}.",
+        name,
+        first + 1,
+        describe(expected.get(first)),
+        describe(actual.get(first))
+    )];
+    for (i, line) in expected
+        .iter()
+        .enumerate()
+        .take(first)
+        .skip(first.saturating_sub(DIFF_CONTEXT_LINES))
+    {
+        diff.push(format!("  {:>6} | {}", i + 1, truncate(line)));
+    }
+
+    let last = total.min(first + DIFF_MAX_LINES);
+    for i in first..last {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => {
+                diff.push(format!("  {:>6} | {}", i + 1, truncate(e)));
+            }
+            (e, a) => {
+                if let Some(line) = e {
+                    diff.push(format!("- {:>6} | {}", i + 1, truncate(line)));
+                }
+                if let Some(line) = a {
+                    diff.push(format!("+ {:>6} | {}", i + 1, truncate(line)));
+                }
+            }
+        }
+    }
+
+    let more = (last..total).filter(|&i| differs(i)).count();
+    if more > 0 {
+        diff.push(format!("... {} more differing lines", more));
+    }
+
+    diff.join("\n")
+}
@@ -0,0 +1,131 @@
+use super::{Input, Judger, Normalization, Output, SpecCommon, SpecInput, DIFF_MAX_WIDTH};
+use async_trait::async_trait;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The maximum size of a compiled pattern, in bytes, so a huge pattern can not exhaust memory.
+const PATTERN_SIZE_LIMIT: usize = 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RegexJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The regular expression the whole normalized output must match
+    pub pattern: String,
+    /// Whether to match the pattern ignoring case
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// How the output is normalized before it is matched
+    #[serde(flatten)]
+    pub normalization: Normalization,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+    /// The anchored pattern, compiled on first use
+    #[serde(skip)]
+    compiled: OnceLock<Regex>,
+}
+
+impl RegexJudgeSpec {
+    /// Compile the pattern once, anchored to match the whole output.
+    /// The regex engine matches in linear time, so no pattern can backtrack catastrophically.
+    fn regex(&self) -> Result<&Regex, String> {
+        if let Some(regex) = self.compiled.get() {
+            return Ok(regex);
+        }
+
+        let build = |pattern: &str| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(self.case_insensitive)
+                .size_limit(PATTERN_SIZE_LIMIT)
+                .build()
+                .map_err(|e| format!("Invalid pattern: {}", e))
+        };
+        // The pattern must be valid on its own, or it could close the group it is anchored in,
+        // like `a)|(b`
+        build(&self.pattern)?;
+        let regex = build(&format!(r"\A(?:{})\z", self.pattern))?;
+        Ok(self.compiled.get_or_init(|| regex))
+    }
+}
+
+#[async_trait]
+impl Judger for RegexJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        self.input.check()?;
+        self.regex()?;
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        let stdout = self.normalization.normalize(&output.stdout_text());
+        if self.regex()?.is_match(&stdout) {
+            return Ok(());
+        }
+
+        let shown = if stdout.chars().count() > DIFF_MAX_WIDTH {
+            format!(
+                "{}...",
+                stdout.chars().take(DIFF_MAX_WIDTH).collect::<String>()
+            )
+        } else {
+            stdout
+        };
+        Err(format!(
+            "Output does not match the pattern `{}`, got `{}`",
+            self.pattern, shown
+        ))
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(pattern: &str) -> RegexJudgeSpec {
+        RegexJudgeSpec {
+            input: SpecInput::default(),
+            pattern: pattern.to_string(),
+            case_insensitive: false,
+            normalization: Normalization::default(),
+            common: SpecCommon::default(),
+            compiled: OnceLock::new(),
+        }
+    }
+
+    #[test]
+    fn pattern_matches_the_whole_output() {
+        let spec = spec("a+|b");
+        let regex = spec.regex().unwrap();
+        assert!(regex.is_match("aaa"));
+        assert!(regex.is_match("b"));
+        assert!(!regex.is_match("aab"));
+    }
+
+    #[test]
+    fn pattern_can_not_escape_its_anchors() {
+        assert!(spec("a)|(b").regex().is_err());
+        assert!(spec(r"a)|.*|(b").regex().is_err());
+    }
+}
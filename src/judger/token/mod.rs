@@ -0,0 +1,87 @@
+use super::{Input, Judger, Output, SpecCommon, SpecInput};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenJudgeSpec {
+    /// The input of the program
+    #[serde(flatten)]
+    pub input: SpecInput,
+    /// The expected output, compared token by token
+    pub expected_output: String,
+    /// Whether to compare tokens ignoring ASCII case
+    #[serde(default)]
+    pub case_insensitive: bool,
+    /// The limits and the scoring of the spec
+    #[serde(flatten)]
+    pub common: SpecCommon,
+}
+
+#[async_trait]
+impl Judger for TokenJudgeSpec {
+    async fn check_spec(&self) -> Result<(), String> {
+        self.common.check()?;
+        self.input.check()?;
+
+        Ok(())
+    }
+
+    async fn make_input(&self) -> Result<Input, String> {
+        self.input.load().await
+    }
+
+    async fn judge_output(&self, _input: &Input, output: &Output) -> Result<(), String> {
+        compare_tokens(
+            &self.expected_output,
+            &output.stdout_text(),
+            self.case_insensitive,
+        )
+    }
+
+    fn limits(&self) -> (u64, u32) {
+        self.common.limits()
+    }
+
+    fn time_limit(&self) -> Option<Duration> {
+        self.common.time_limit()
+    }
+
+    fn scoring(&self) -> (Option<String>, Option<f64>) {
+        self.common.scoring()
+    }
+}
+
+/// Compare two outputs token by token, ignoring ASCII case if `case_insensitive` is set.
+pub fn compare_tokens(expected: &str, actual: &str, case_insensitive: bool) -> Result<(), String> {
+    let mut expected = expected.split_ascii_whitespace();
+    let mut actual = actual.split_ascii_whitespace();
+
+    let mut index = 0;
+    loop {
+        match (expected.next(), actual.next()) {
+            (None, None) => return Ok(()),
+            (Some(e), Some(a)) if e == a => {}
+            (Some(e), Some(a)) if case_insensitive && e.eq_ignore_ascii_case(a) => {}
+            (Some(e), Some(a)) => {
+                return Err(format!(
+                    "Token mismatch at index {}. Expected `{}`, got `{}`",
+                    index, e, a
+                ))
+            }
+            (Some(e), None) => {
+                return Err(format!(
+                    "Token mismatch at index {}. Expected `{}`, got end of output",
+                    index, e
+                ))
+            }
+            (None, Some(a)) => {
+                return Err(format!(
+                    "Token mismatch at index {}. Expected end of output, got `{}`",
+                    index, a
+                ))
+            }
+        }
+        index += 1;
+    }
+}
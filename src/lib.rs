@@ -1,8 +1,13 @@
+pub mod args;
+pub mod cache;
 pub mod cli;
+pub mod clock;
 pub mod config;
 pub mod cost;
 pub mod judger;
 pub mod memory;
+pub mod permit;
+pub mod random;
 pub mod read;
 pub mod run;
 pub mod server;
@@ -1,6 +1,9 @@
+use base64::{engine::general_purpose, Engine as _};
+use clap_complete::Shell;
 use rocket::tokio::task;
 use serde_json::json;
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::{fs, process};
 use std::{io, io::prelude::*};
 use tracing::Level;
@@ -19,16 +22,38 @@ async fn main() {
             let cost: u64 = *args
                 .get_one("cost")
                 .expect("cost limit should be in range 1..");
+            let compiler: run::Compiler = args
+                .get_one::<String>("compiler")
+                .expect("compiler should be provided")
+                .parse()
+                .expect("compiler should be cranelift or singlepass");
+            let strict: bool = *args.get_one("strict").unwrap_or(&false);
             let input: &String = args
                 .get_one("input")
                 .expect("input file path should be provided");
+            let stdout: Option<&PathBuf> = args.get_one("stdout");
             let stderr: Option<&PathBuf> = args.get_one("stderr");
             let no_report: &bool = args.get_one("no-report").unwrap_or(&false);
+            let as_json: &bool = args.get_one("json").unwrap_or(&false);
+            let operations: &bool = args.get_one("operations").unwrap_or(&false);
+            let seed: Option<u64> = args.get_one("seed").copied();
+            let clock_start: Option<u64> = args.get_one("clock-start").copied();
+            let clock_increment: Option<u64> = args.get_one("clock-increment").copied();
+            let precompiled: bool = *args.get_one("precompiled").unwrap_or(&false);
             let module: &PathBuf = args
                 .get_one("module")
                 .expect("module path should be provided");
 
-            let wasm = read::read_wasm(module.to_path_buf()).expect("Failed to read wasm module");
+            if module.as_os_str() == "-" && input == "-" {
+                eprintln!("The module and the input can not both be read from stdin");
+                process::exit(1);
+            }
+
+            let wasm = if precompiled {
+                read_precompiled(module)
+            } else {
+                read_module(module, args.get_one("module-auth")).await
+            };
 
             let input = match input.as_str() {
                 "" => String::new(),
@@ -42,25 +67,94 @@ async fn main() {
                 _ => fs::read_to_string(input).expect("Failed to read input file"),
             };
 
-            let handle = task::spawn_blocking(move || run::run(wasm, cost, mem, input));
+            let clock_mode = match (clock_start, clock_increment) {
+                (None, None) => clock::ClockMode::System,
+                (start, increment) => clock::ClockMode::Deterministic {
+                    start: start.unwrap_or(clock::DEFAULT_CLOCK_START),
+                    increment: increment.unwrap_or(clock::DEFAULT_CLOCK_INCREMENT),
+                },
+            };
+
+            let random_mode = random::RandomMode::from_seed(seed);
+            let handle = task::spawn_blocking(move || {
+                if precompiled {
+                    let compiled = run::load_precompiled(&wasm)?
+                        .with_random_mode(random_mode)
+                        .with_clock_mode(clock_mode);
+                    return run::run_module(&compiled, input);
+                }
+
+                run::run(run::RunRequest {
+                    wasm,
+                    budget: cost,
+                    mem,
+                    input,
+                    metering: true,
+                    timeout: None,
+                    random_mode,
+                    clock_mode,
+                    compiler,
+                    canonicalize_nans: config::config().canonicalize_nans,
+                    strict: strict || config::config().strict,
+                })
+            });
 
             let result = match handle.await.unwrap() {
                 Ok(result) => result,
+                Err(e) if *as_json => {
+                    println!("{}", json!({ "error": e.to_string() }));
+                    process::exit(1);
+                }
                 Err(e) => {
-                    eprintln!("{:?}", e);
+                    eprintln!("{}", e);
                     process::exit(1);
                 }
             };
 
-            print!(
-                "{}",
-                String::from_utf8(result.stdout).expect("Failed to convert stdout to string")
-            );
+            if *as_json {
+                let mut report = json!({
+                    "exit_code": result.exit_code,
+                    "cost": result.cost,
+                    "memory": result.memory,
+                    "memory_bytes": result.memory_bytes,
+                    "memory_grows": result.memory_grows,
+                    "stdout": general_purpose::STANDARD.encode(&result.stdout),
+                    "stderr": general_purpose::STANDARD.encode(&result.stderr),
+                });
+                if *operations {
+                    report["operations"] = json!(result.operations);
+                }
+                println!("{}", report);
+
+                if let Some(stdout) = stdout {
+                    fs::write(stdout, result.stdout).expect("Failed to write stdout to file");
+                }
+                if let Some(stderr) = stderr {
+                    fs::write(stderr, result.stderr).expect("Failed to write stderr to file");
+                }
+
+                if result.exit_code != 0 {
+                    process::exit(result.exit_code);
+                }
+                return;
+            }
+
+            match stdout {
+                Some(stdout) => {
+                    fs::write(stdout, &result.stdout).expect("Failed to write stdout to file")
+                }
+                None => io::stdout()
+                    .write_all(&result.stdout)
+                    .expect("Failed to write stdout"),
+            }
 
             if !no_report {
                 let stats = json!({
+                    "exit_code": result.exit_code,
                     "cost": result.cost,
                     "memory": result.memory,
+                    "memory_bytes": result.memory_bytes,
+                    "memory_grows": result.memory_grows,
                 });
                 eprintln!(
                     "{}",
@@ -68,9 +162,169 @@ async fn main() {
                 );
             }
 
+            if *operations {
+                for (name, count) in sort_operations(result.operations) {
+                    eprintln!("{:<32}{}", name, count);
+                }
+            }
+
             if let Some(stderr) = stderr {
                 fs::write(stderr, result.stderr).expect("Failed to write stderr to file");
             }
+
+            if result.exit_code != 0 {
+                process::exit(result.exit_code);
+            }
+        }
+        Some(("check", args)) => {
+            let mem: u32 = *args
+                .get_one("memory")
+                .expect("memory limit should be in range 1..");
+            let cost: u64 = *args
+                .get_one("cost")
+                .expect("cost limit should be in range 1..");
+            let compiler: run::Compiler = args
+                .get_one::<String>("compiler")
+                .expect("compiler should be provided")
+                .parse()
+                .expect("compiler should be cranelift or singlepass");
+            let strict: bool = *args.get_one("strict").unwrap_or(&false);
+            let module: &PathBuf = args
+                .get_one("module")
+                .expect("module path should be provided");
+
+            let wasm = read_module(module, args.get_one("module-auth")).await;
+
+            let compiled = match run::compile(
+                &wasm,
+                cost,
+                mem,
+                run::CompileOptions {
+                    compiler,
+                    strict: strict || config::config().strict,
+                    ..config::config().compile_options()
+                },
+            ) {
+                Ok(compiled) => compiled,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            for (name, count) in sort_operations(compiled.penalty_operations()) {
+                eprintln!(
+                    "Penalty operation {} ({} times) costs {} each",
+                    name,
+                    count,
+                    cost::PENALTY_COST
+                );
+            }
+        }
+        Some(("compile-to", args)) => {
+            let mem: u32 = *args
+                .get_one("memory")
+                .expect("memory limit should be in range 1..");
+            let cost: u64 = *args
+                .get_one("cost")
+                .expect("cost limit should be in range 1..");
+            let module: &PathBuf = args
+                .get_one("module")
+                .expect("module path should be provided");
+            let out: &PathBuf = args.get_one("out").expect("output path should be provided");
+            let compiler: run::Compiler = args
+                .get_one::<String>("compiler")
+                .expect("compiler should be provided")
+                .parse()
+                .expect("compiler should be cranelift or singlepass");
+            let strict: bool = *args.get_one("strict").unwrap_or(&false);
+
+            let wasm = read_module(module, args.get_one("module-auth")).await;
+
+            let options = run::CompileOptions {
+                compiler,
+                strict: strict || config::config().strict,
+                ..config::config().compile_options()
+            };
+            let precompiled = match run::precompile(&wasm, cost, mem, options) {
+                Ok(precompiled) => precompiled,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            fs::write(out, precompiled).expect("Failed to write precompiled module to file");
+        }
+        Some(("judge", args)) => {
+            let module: &PathBuf = args
+                .get_one("module")
+                .expect("module path should be provided");
+            let spec: &PathBuf = args.get_one("spec").expect("spec path should be provided");
+
+            let wasm = read_module(module, args.get_one("module-auth")).await;
+
+            let spec = match judger::load_spec(spec) {
+                Ok(spec) => spec,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            let results = server::run_specs(wasm, vec![spec]).await;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&results).expect("Failed to serialize results")
+            );
+
+            if !results.success() {
+                process::exit(1);
+            }
+        }
+        Some(("analyze", args)) => {
+            let module: &PathBuf = args
+                .get_one("module")
+                .expect("module path should be provided");
+
+            let wasm = read_module(module, args.get_one("module-auth")).await;
+
+            let operations = match run::analyze(&wasm) {
+                Ok(operations) => operations,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            for (name, count) in sort_operations(operations) {
+                println!("{:<32}{}", name, count);
+            }
+        }
+        Some(("costs", args)) => {
+            let as_json: &bool = args.get_one("json").unwrap_or(&false);
+            if *as_json {
+                let costs: HashMap<&str, u64> = cost::OPERATOR_COSTS.iter().copied().collect();
+                println!(
+                    "{}",
+                    json!({ "costs": costs, "penalty": cost::PENALTY_COST })
+                );
+                return;
+            }
+
+            for (name, points) in cost::OPERATOR_COSTS {
+                println!(
+                    "{:<40}{:<12}{}",
+                    name,
+                    cost::operation_category(name),
+                    points
+                );
+            }
+            println!("{:<40}{:<12}{}", "(any other)", "", cost::PENALTY_COST);
+        }
+        Some(("completions", args)) => {
+            let shell: Shell = *args.get_one("shell").expect("shell should be provided");
+            clap_complete::generate(shell, &mut cli::cli(), "wark", &mut io::stdout());
         }
         Some(("server", _)) => {
             match FmtSubscriber::builder()
@@ -83,6 +337,9 @@ async fn main() {
                     process::exit(1);
                 }
             }
+            if !server::core::check_app_secret() {
+                process::exit(1);
+            }
             let _ = server::core::rocket().launch().await;
         }
         Some(_) | None => {
@@ -90,3 +347,50 @@ async fn main() {
         }
     }
 }
+
+/// Sort the operation counts by count in descending order, then by name.
+fn sort_operations(operations: HashMap<String, u64>) -> Vec<(String, u64)> {
+    let mut operations: Vec<_> = operations.into_iter().collect();
+    operations.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    operations
+}
+
+/// Read the wasm module at the path, or fetch it if it is a URL, exiting with the error if it can
+/// not be read.
+async fn read_module(module: &Path, auth: Option<&String>) -> Box<[u8]> {
+    let wasm = match module.to_str() {
+        Some(url) if read::is_url(url) => read::fetch_wasm(url, &auth.cloned()).await,
+        _ => read::read_wasm(module.to_path_buf()),
+    };
+
+    match wasm {
+        Ok(wasm) => wasm,
+        Err(e) => {
+            eprintln!("Failed to read wasm module {}: {}", module.display(), e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Read a module precompiled with `compile-to` from a file, or from stdin if the path is `-`,
+/// exiting with the error if it can not be read.
+fn read_precompiled(module: &Path) -> Box<[u8]> {
+    let bytes = if module.as_os_str() == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        fs::read(module)
+    };
+
+    match bytes {
+        Ok(bytes) => bytes.into_boxed_slice(),
+        Err(e) => {
+            eprintln!(
+                "Failed to read precompiled module {}: {}",
+                module.display(),
+                e
+            );
+            process::exit(1);
+        }
+    }
+}
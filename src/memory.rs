@@ -6,6 +6,10 @@ use wasmer::{
     MemoryType, Pages, TableType, Tunables,
 };
 
+/// The message of the errors creating a memory beyond the limit, to tell them apart from other
+/// instantiation errors.
+pub const MEMORY_LIMIT_ERROR: &str = "Memory limit exceeded";
+
 /// A custom tunables that allows you to set a memory limit.
 ///
 /// After adjusting the memory limits, it delegates all other logic
@@ -40,16 +44,18 @@ impl<T: Tunables> LimitingTunables<T> {
     /// Call this after adjusting the memory.
     fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
         if ty.minimum > self.limit {
-            return Err(MemoryError::Generic(
-                "Minimum exceeds the allowed memory limit".to_string(),
-            ));
+            return Err(MemoryError::Generic(format!(
+                "{}: minimum exceeds the allowed memory limit",
+                MEMORY_LIMIT_ERROR
+            )));
         }
 
         if let Some(max) = ty.maximum {
             if max > self.limit {
-                return Err(MemoryError::Generic(
-                    "Maximum exceeds the allowed memory limit".to_string(),
-                ));
+                return Err(MemoryError::Generic(format!(
+                    "{}: maximum exceeds the allowed memory limit",
+                    MEMORY_LIMIT_ERROR
+                )));
             }
         } else {
             return Err(MemoryError::Generic("Maximum unset".to_string()));
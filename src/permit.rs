@@ -0,0 +1,24 @@
+use crate::config::max_concurrent_runs;
+use rocket::tokio::sync::{Semaphore, SemaphorePermit};
+use std::sync::OnceLock;
+
+/// The permits to run a module, shared by the server and the judgers.
+static RUN_PERMITS: OnceLock<Semaphore> = OnceLock::new();
+
+fn run_permits() -> &'static Semaphore {
+    RUN_PERMITS.get_or_init(|| Semaphore::new(max_concurrent_runs()))
+}
+
+/// The number of modules that can start running right away.
+pub fn available_run_permits() -> usize {
+    run_permits().available_permits()
+}
+
+/// Wait for a permit to run a module, so no more than `MAX_CONCURRENT_RUNS` modules are compiled
+/// or run at the same time. Runs beyond the limit are queued until a permit is released.
+pub async fn acquire_run_permit() -> SemaphorePermit<'static> {
+    run_permits()
+        .acquire()
+        .await
+        .expect("The run semaphore is never closed")
+}
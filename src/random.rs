@@ -0,0 +1,129 @@
+use crate::run::WASI_NAMESPACES;
+use wasmer::{AsStoreMut, Function, FunctionEnv, FunctionEnvMut, Imports, Memory, WasmPtr};
+use wasmer_wasix::wasmer_wasix_types::wasi::Errno;
+
+/// The number of random bytes written to the memory of the guest at a time.
+const RANDOM_CHUNK_SIZE: usize = 4096;
+
+/// Where the random bytes of a run come from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RandomMode {
+    /// Real entropy from the host, so runs are not reproducible.
+    #[default]
+    System,
+    /// A PRNG seeded with the given seed, so runs with the same seed get the same bytes.
+    Deterministic { seed: u64 },
+}
+
+impl RandomMode {
+    /// Deterministic with the given seed, if any, or real entropy otherwise.
+    pub fn from_seed(seed: Option<u64>) -> Self {
+        match seed {
+            Some(seed) => RandomMode::Deterministic { seed },
+            None => RandomMode::System,
+        }
+    }
+}
+
+/// A small and fast seedable PRNG (xorshift64*), whose stream only depends on its seed.
+#[derive(Debug, Clone)]
+pub struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    pub fn new(seed: u64) -> Self {
+        // Scramble the seed with SplitMix64, so close seeds give unrelated streams
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        // The state of xorshift must not be zero
+        Prng {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+struct RandomEnv {
+    memory: Option<Memory>,
+    prng: Prng,
+}
+
+/// A deterministic `random_get` for a single run, filling the buffers of the guest from a PRNG
+/// seeded with the seed of the run, so the same seed always gives the guest the same bytes.
+pub struct DeterministicRandom {
+    env: FunctionEnv<RandomEnv>,
+}
+
+impl DeterministicRandom {
+    /// Replace the `random_get` of the WASI imports with one seeded with the given seed.
+    pub fn new(store: &mut impl AsStoreMut, imports: &mut Imports, seed: u64) -> Self {
+        let env = FunctionEnv::new(
+            store,
+            RandomEnv {
+                memory: None,
+                prng: Prng::new(seed),
+            },
+        );
+
+        for namespace in WASI_NAMESPACES {
+            let random_get = Function::new_typed_with_env(store, &env, random_get);
+            imports.define(namespace, "random_get", random_get);
+        }
+
+        DeterministicRandom { env }
+    }
+
+    /// Give the function the memory of the instance to write the random bytes to.
+    pub fn initialize(&self, store: &mut impl AsStoreMut, memory: Memory) {
+        self.env.as_mut(store).memory = Some(memory);
+    }
+}
+
+fn random_get(mut env: FunctionEnvMut<RandomEnv>, buf: WasmPtr<u8>, len: u32) -> i32 {
+    let (data, store) = env.data_and_store_mut();
+    let memory = match &data.memory {
+        Some(memory) => memory,
+        None => return Errno::Fault as i32,
+    };
+
+    // The guest chooses the length, so check it against the memory before writing anything
+    let view = memory.view(&store);
+    let start = buf.offset() as u64;
+    if start + len as u64 > view.data_size() {
+        return Errno::Fault as i32;
+    }
+
+    // Fill the buffer a chunk at a time rather than allocating `len` bytes on the host. The
+    // chunks are a multiple of 8 bytes, so the bytes are the same as from a single fill.
+    let mut chunk = [0; RANDOM_CHUNK_SIZE];
+    let mut offset = 0;
+    while offset < len as u64 {
+        let size = (len as u64 - offset).min(RANDOM_CHUNK_SIZE as u64) as usize;
+        data.prng.fill(&mut chunk[..size]);
+        if view.write(start + offset, &chunk[..size]).is_err() {
+            return Errno::Fault as i32;
+        }
+        offset += size as u64;
+    }
+
+    Errno::Success as i32
+}
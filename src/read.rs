@@ -1,11 +1,110 @@
+use crate::judger;
 use std::fs::File;
-use std::io::{Error, Read};
-use std::path::PathBuf;
+use std::io::{self, Error, ErrorKind, Read};
+use std::path::{Path, PathBuf};
 
-/// Read a wasm module from a file
+/// The magic bytes every binary module starts with.
+const WASM_MAGIC: &[u8] = b"\0asm";
+
+/// The version of the binary format following the magic bytes.
+const WASM_VERSION: [u8; 4] = [1, 0, 0, 0];
+
+/// The maximum size of a module fetched from a URL, in bytes.
+const MAX_MODULE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Read a wasm module from a file, or from stdin if the path is `-`.
+/// Modules in the WebAssembly text format are compiled to binary.
 pub fn read_wasm(path: PathBuf) -> Result<Box<[u8]>, Error> {
-    let mut file = File::open(path).expect("wasm module not found");
     let mut wasm_bytes = Vec::new();
-    file.read_to_end(&mut wasm_bytes)?;
+    if path.as_os_str() == "-" {
+        io::stdin().lock().read_to_end(&mut wasm_bytes)?;
+    } else {
+        let mut file = File::open(&path)?;
+        file.read_to_end(&mut wasm_bytes)?;
+    }
+
+    parse_module(&path, wasm_bytes)
+}
+
+/// Fetch a wasm module from an http(s) URL, with an optional bearer token.
+/// The download is limited to `MAX_MODULE_SIZE` bytes.
+pub async fn fetch_wasm(url: &str, auth: &Option<String>) -> Result<Box<[u8]>, Error> {
+    let wasm_bytes = judger::fetch_bytes("module", url, auth, MAX_MODULE_SIZE)
+        .await
+        .map_err(|e| Error::new(ErrorKind::Other, e))?;
+
+    let path = url.rsplit('/').next().unwrap_or_default();
+    parse_module(Path::new(path), wasm_bytes)
+}
+
+/// Whether a module argument is an http(s) URL rather than a path.
+pub fn is_url(module: &str) -> bool {
+    module.starts_with("http://") || module.starts_with("https://")
+}
+
+/// Compile a module in the text format to binary, or check the header of a binary module.
+fn parse_module(path: &Path, mut wasm_bytes: Vec<u8>) -> Result<Box<[u8]>, Error> {
+    if is_wat(path, &wasm_bytes) {
+        wasm_bytes = parse_wat(path, &wasm_bytes)?;
+    } else {
+        check_header(&wasm_bytes)?;
+    }
+
     Ok(wasm_bytes.into_boxed_slice())
 }
+
+/// Check the magic bytes and the version of a binary module, so passing the wrong file fails
+/// right away instead of with an opaque compile error.
+fn check_header(bytes: &[u8]) -> Result<(), Error> {
+    if !bytes.starts_with(WASM_MAGIC) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "not a WebAssembly module",
+        ));
+    }
+
+    match bytes.get(4..8) {
+        Some(version) if *version == WASM_VERSION => Ok(()),
+        Some(version) => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "unsupported WebAssembly version {}",
+                u32::from_le_bytes(version.try_into().unwrap())
+            ),
+        )),
+        None => Err(Error::new(
+            ErrorKind::InvalidData,
+            "truncated WebAssembly module",
+        )),
+    }
+}
+
+/// Whether a module is in the text format, by its extension or by its leading `(`.
+fn is_wat(path: &Path, bytes: &[u8]) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str());
+    if matches!(extension, Some("wat") | Some("wast")) {
+        return true;
+    }
+
+    bytes
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .map_or(false, |&byte| byte == b'(')
+}
+
+/// Compile a module in the text format to binary, with the offending line in the error.
+fn parse_wat(path: &Path, text: &[u8]) -> Result<Vec<u8>, Error> {
+    let text = std::str::from_utf8(text).map_err(|e| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid text module: {}", e),
+        )
+    })?;
+
+    wat::parse_str(text).map_err(|mut e| {
+        if path.as_os_str() != "-" {
+            e.set_path(path);
+        }
+        Error::new(ErrorKind::InvalidData, e.to_string())
+    })
+}
@@ -1,86 +1,887 @@
-use crate::cost::{get_remaining_points, Cost, CostPoints};
-use crate::memory::LimitingTunables;
+use crate::args::{DeterministicArgs, PROGRAM_NAME};
+use crate::cache::module_cache;
+use crate::clock::{Clock, ClockMode};
+use crate::config::config;
+use crate::cost::{
+    categorize_operations, get_failed_memory_grows, get_function_costs, get_memory_grows,
+    get_remaining_points, listed_cost, operation_category, operator_name, Cost, CostPoints,
+    DEFAULT_EXPORT_PREFIX,
+};
+use crate::memory::{LimitingTunables, MEMORY_LIMIT_ERROR};
+use crate::random::{DeterministicRandom, RandomMode};
+use rocket::tokio::sync::mpsc::UnboundedSender;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
 use std::io::{Read, Write};
-use std::sync::Arc;
+use std::panic::{self, AssertUnwindSafe};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use wasmer::wasmparser::{Parser, Payload, TypeRef};
 use wasmer::{BaseTunables, CompilerConfig, Engine, Memory, Pages, Target};
-use wasmer::{Cranelift, Instance};
+use wasmer::{Cranelift, Instance, Singlepass};
 use wasmer::{Module, Store};
 use wasmer_types::TrapCode;
 use wasmer_wasix::wasmer_wasix_types::wasi::ExitCode;
 use wasmer_wasix::{wasmer_wasix_types, Pipe, WasiEnv, WasiError};
 
+/// The WASI namespaces with 32-bit pointers, whose functions are replaced by the deterministic
+/// arguments, clocks and random bytes of a run.
+pub(crate) const WASI_NAMESPACES: [&str; 3] =
+    ["wasi_unstable", "wasi_snapshot_preview1", "wasix_32v1"];
+
 #[derive(Debug)]
+pub struct RunRequest {
+    /// The WebAssembly module to run.
+    pub wasm: Box<[u8]>,
+    /// The cost limit of the program.
+    pub budget: u64,
+    /// The memory limit of the program, in MB.
+    pub mem: u32,
+    /// The stdin of the program.
+    pub input: String,
+    /// Whether to meter the program with the Cost middleware.
+    /// Trusted modules can skip it to avoid the overhead, their cost is reported as 0.
+    pub metering: bool,
+    /// The wall-clock time limit of the program, unlimited if not set.
+    pub timeout: Option<Duration>,
+    /// Where the random bytes of the program come from.
+    pub random_mode: RandomMode,
+    /// What the clocks of the program read.
+    pub clock_mode: ClockMode,
+    /// The compiler backend to compile the program with.
+    pub compiler: Compiler,
+    /// Whether to canonicalize the NaNs produced by floating-point operations.
+    pub canonicalize_nans: bool,
+    /// Whether to reject modules using SIMD or threads.
+    pub strict: bool,
+}
+
+impl RunRequest {
+    /// Check that the cost and memory limits of the request are within the given maxima, so a
+    /// request can not make the runtime allocate more memory than the host allows.
+    pub fn validate(&self, max_cost: u64, max_memory: u32) -> Result<(), RunError> {
+        check_limits(self.budget, self.mem, max_cost, max_memory).map_err(RunError::CompileError)
+    }
+}
+
+/// Check that the cost and memory limits are within the given maxima.
+pub fn check_limits(budget: u64, mem: u32, max_cost: u64, max_memory: u32) -> Result<(), String> {
+    if budget > max_cost {
+        return Err(format!(
+            "Invalid cost limit, got {}, max is {}",
+            budget, max_cost
+        ));
+    }
+
+    if mem > max_memory {
+        return Err(format!(
+            "Invalid memory limit, got {}, max is {}",
+            mem, max_memory
+        ));
+    }
+
+    Ok(())
+}
+
+/// How a module is compiled, besides the limits baked into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CompileOptions {
+    /// Whether to meter the module with the Cost middleware.
+    pub metered: bool,
+    /// The compiler backend to compile the module with.
+    pub compiler: Compiler,
+    /// Whether every NaN produced by a floating-point operation has the canonical bit pattern,
+    /// instead of a payload depending on the backend and the CPU, so programs reinterpreting
+    /// floats as integers get the same bits on every host.
+    pub canonicalize_nans: bool,
+    /// Whether to reject modules using SIMD, including relaxed SIMD, or threads, whose results
+    /// may depend on the host, and which widen the surface of the runtime exposed to modules.
+    pub strict: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            metered: true,
+            compiler: Compiler::default(),
+            canonicalize_nans: true,
+            strict: false,
+        }
+    }
+}
+
+/// The compiler backend of a module. Both meter modules with the same Cost middleware, so the
+/// cost of a program does not depend on the backend.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Compiler {
+    /// Compiles slowly to fast code, for long-running programs.
+    #[default]
+    Cranelift,
+    /// Compiles quickly to slower code, for short-lived programs where compiling dominates.
+    Singlepass,
+}
+
+impl FromStr for Compiler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cranelift" => Ok(Compiler::Cranelift),
+            "singlepass" => Ok(Compiler::Singlepass),
+            _ => Err(format!(
+                "Unknown compiler {}, supported compilers are cranelift and singlepass",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct RunResult {
+    /// The exit code of the program, 0 if it returned from `_start` without calling `proc_exit`.
+    pub exit_code: i32,
     /// The cost of the program.
     pub cost: u64,
-    /// The memory usage of the program, in MB.
+    /// The memory usage of the program, in MB, rounded up.
     pub memory: u32,
+    /// The memory usage of the program, in bytes.
+    /// A linear memory never shrinks, so its final size is also its peak size.
+    pub memory_bytes: u64,
+    /// The number of `memory.grow` operations the program executed, 0 if it was not metered.
+    pub memory_grows: u64,
+    /// The wall-clock time the program ran for, from the call of its `_start` function to its
+    /// return, excluding compiling and instantiating it.
+    pub exec_time: Duration,
     /// The stdout of the program.
     pub stdout: Vec<u8>,
     /// The stderr of the program.
     pub stderr: Vec<u8>,
     /// The operations counts of the program. (instruction counts, not runtime costs)
-    pub operations: std::collections::HashMap<String, u64>,
+    pub operations: HashMap<String, u64>,
+    /// The operations counts of the program, rolled up by operator category.
+    pub operation_categories: HashMap<String, u64>,
+    /// The static cost of each operator of the program, its count multiplied by its cost.
+    /// The runtime cost of bulk memory operations is not included.
+    pub cost_by_operator: HashMap<String, u64>,
+    /// The cost spent by each function of the program, keyed by local function index.
+    pub function_costs: HashMap<u32, u64>,
+    /// The names of the functions in `function_costs`, where the module provides them.
+    pub function_names: HashMap<u32, String>,
+    /// The verdict of the checker the program interacted with, set by the interactive judger.
+    #[serde(default)]
+    pub verdict: Option<String>,
 }
 
-#[derive(Debug)]
+/// Serialized with the judge exception code of the error as its `type`, and its value as its
+/// `reason`, like `{"type":"MLE","reason":32}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "reason")]
 pub enum RunError {
+    #[serde(rename = "SLE")]
     SpendingLimitExceeded(u64),
+    #[serde(rename = "MLE")]
     MemoryLimitExceeded(u32),
+    /// The wall-clock time limit, in milliseconds.
+    #[serde(rename = "TLE")]
+    TimeLimitExceeded(u64),
+    #[serde(rename = "RE")]
     RuntimeError(String),
+    #[serde(rename = "CE")]
     CompileError(String),
+    #[serde(rename = "IOE")]
     IOError(String),
 }
 
-pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunResult, RunError> {
-    let metering = Arc::new(Cost::new(budget));
+impl RunError {
+    /// The judge exception code of the error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            RunError::SpendingLimitExceeded(_) => "SLE",
+            RunError::MemoryLimitExceeded(_) => "MLE",
+            RunError::TimeLimitExceeded(_) => "TLE",
+            RunError::RuntimeError(_) => "RE",
+            RunError::CompileError(_) => "CE",
+            RunError::IOError(_) => "IOE",
+        }
+    }
+
+    /// The error of a run that panicked, a bug of the runtime rather than of the module.
+    pub fn internal_panic() -> Self {
+        RunError::RuntimeError("internal panic".to_string())
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::SpendingLimitExceeded(budget) => {
+                write!(f, "Cost limit of {} exceeded", budget)
+            }
+            RunError::MemoryLimitExceeded(mem) => write!(f, "Memory limit of {} MB exceeded", mem),
+            RunError::TimeLimitExceeded(ms) => write!(f, "Time limit of {} ms exceeded", ms),
+            RunError::RuntimeError(e) => write!(f, "Runtime error: {}", e),
+            RunError::CompileError(e) => write!(f, "Compile error: {}", e),
+            RunError::IOError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RunError {}
+
+/// Compile the module with the Cost middleware and return its static operation counts,
+/// without instantiating or executing it.
+pub fn analyze(wasm: &[u8]) -> Result<HashMap<String, u64>, RunError> {
+    let metering = Arc::new(Cost::new(0, DEFAULT_EXPORT_PREFIX));
     let mut compiler = Cranelift::default();
     compiler.push_middleware(metering.clone());
 
+    let store = Store::new(compiler);
+    Module::new(&store, wasm).map_err(|e| RunError::CompileError(e.to_string()))?;
+
+    let operations = metering.operation_counts.lock().unwrap().clone();
+    Ok(operations)
+}
+
+/// A module compiled with the limits of a run, which can be run many times.
+/// Each run gets a fresh store, so no memory or cost points are shared between runs.
+/// Clones share the compiled code, so they are cheap.
+#[derive(Clone)]
+pub struct CompiledModule {
+    engine: Engine,
+    module: Module,
+    metering: Arc<Cost>,
+    budget: u64,
+    mem: u32,
+    metered: bool,
+    random_mode: RandomMode,
+    clock_mode: ClockMode,
+}
+
+impl CompiledModule {
+    /// Set where the random bytes of the runs of the module come from.
+    pub fn with_random_mode(mut self, random_mode: RandomMode) -> Self {
+        self.random_mode = random_mode;
+        self
+    }
+
+    /// Set what the clocks of the runs of the module read.
+    pub fn with_clock_mode(mut self, clock_mode: ClockMode) -> Self {
+        self.clock_mode = clock_mode;
+        self
+    }
+
+    /// The operations of the module missing from the cost table, which cost `PENALTY_COST` each,
+    /// with their counts.
+    pub fn penalty_operations(&self) -> HashMap<String, u64> {
+        let counts = self.metering.operation_counts.lock().unwrap();
+        counts
+            .iter()
+            .filter(|&(name, _)| listed_cost(name).is_none())
+            .map(|(name, &count)| (name.clone(), count))
+            .collect()
+    }
+}
+
+/// Compile the module with the given limits, the cost limit is baked into the compiled module.
+/// Modules compiled before with the same limits and options are reused from the module cache.
+pub fn compile(
+    wasm: &[u8],
+    budget: u64,
+    mem: u32,
+    options: CompileOptions,
+) -> Result<CompiledModule, RunError> {
+    catch_panic(|| {
+        module_cache().get_or_compile(wasm, budget, mem, options, || {
+            compile_uncached(wasm, budget, mem, options)
+        })
+    })
+}
+
+/// Compile the module with the given limits, bypassing the module cache.
+pub fn compile_uncached(
+    wasm: &[u8],
+    budget: u64,
+    mem: u32,
+    options: CompileOptions,
+) -> Result<CompiledModule, RunError> {
+    if options.strict {
+        validate_strict(wasm)?;
+    }
+
+    let metering = Arc::new(Cost::new(budget, DEFAULT_EXPORT_PREFIX));
+    let engine = engine(&metering, mem, options);
+
+    let module = Module::new(&engine, wasm).map_err(|e| RunError::CompileError(e.to_string()))?;
+    validate_imports(&module, &config().allowed_imports)?;
+
+    Ok(CompiledModule {
+        engine,
+        module,
+        metering,
+        budget,
+        mem,
+        metered: options.metered,
+        random_mode: RandomMode::System,
+        clock_mode: ClockMode::System,
+    })
+}
+
+/// Check that the module only imports from the allowed namespaces, so a module importing a host
+/// function wark does not provide fails with the name of the import, instead of with an opaque
+/// error when it is instantiated.
+pub fn validate_imports(module: &Module, allowed: &[String]) -> Result<(), RunError> {
+    for import in module.imports() {
+        if !allowed.iter().any(|namespace| namespace == import.module()) {
+            return Err(RunError::CompileError(format!(
+                "Import {}.{} is not allowed, modules can only import from {}",
+                import.module(),
+                import.name(),
+                allowed.join(", ")
+            )));
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that the module uses neither SIMD nor threads, naming the first instruction or shared
+/// memory using them. Malformed modules are left for the compiler to report.
+pub fn validate_strict(wasm: &[u8]) -> Result<(), RunError> {
+    let reject = |what: String| {
+        Err(RunError::CompileError(format!(
+            "{} is not allowed in strict mode",
+            what
+        )))
+    };
+
+    let mut imported_functions = 0;
+    let mut function = 0;
+    for payload in Parser::new(0).parse_all(wasm) {
+        let payload = match payload {
+            Ok(payload) => payload,
+            Err(_) => return Ok(()),
+        };
+        match payload {
+            Payload::ImportSection(reader) => {
+                for import in reader.into_iter().flatten() {
+                    match import.ty {
+                        TypeRef::Func(_) => imported_functions += 1,
+                        TypeRef::Memory(memory) if memory.shared => {
+                            return reject(format!(
+                                "Shared memory {}.{}",
+                                import.module, import.name
+                            ))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Payload::MemorySection(reader) => {
+                if reader.into_iter().flatten().any(|memory| memory.shared) {
+                    return reject("Shared memory".to_string());
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                let mut reader = match body.get_operators_reader() {
+                    Ok(reader) => reader,
+                    Err(_) => return Ok(()),
+                };
+                while !reader.eof() {
+                    let operator = match reader.read() {
+                        Ok(operator) => operator,
+                        Err(_) => return Ok(()),
+                    };
+                    let name = operator_name(&operator);
+                    let feature = match operation_category(&name) {
+                        "simd" => "SIMD",
+                        "atomic" => "threads",
+                        _ => continue,
+                    };
+                    return reject(format!(
+                        "Instruction {} ({}) in function {}",
+                        name,
+                        feature,
+                        imported_functions + function
+                    ));
+                }
+                function += 1;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// The engine compiling modules with the given options and the Cost middleware, if metered, and
+/// limiting their memory.
+fn engine(metering: &Arc<Cost>, mem: u32, options: CompileOptions) -> Engine {
+    let mut engine = match options.compiler {
+        Compiler::Cranelift => metered_engine(Cranelift::default(), metering, options),
+        Compiler::Singlepass => metered_engine(Singlepass::default(), metering, options),
+    };
+
     let base = BaseTunables::for_target(&Target::default());
     let tunables = LimitingTunables::new(base, Pages(mem * 16));
-
-    let mut engine: Engine = compiler.into();
     engine.set_tunables(tunables);
+    engine
+}
+
+fn metered_engine<C>(mut compiler: C, metering: &Arc<Cost>, options: CompileOptions) -> Engine
+where
+    C: CompilerConfig + Into<Engine>,
+{
+    compiler.canonicalize_nans(options.canonicalize_nans);
+    if options.metered {
+        compiler.push_middleware(metering.clone());
+    }
+    compiler.into()
+}
+
+/// The magic bytes of a precompiled module.
+const PRECOMPILED_MAGIC: &[u8] = b"\0wark";
+
+/// The version of wark a precompiled module must have been compiled by, since the artifacts of
+/// wasmer and the cost table both change between versions.
+const PRECOMPILED_VERSION: &str = concat!(env!("CARGO_PKG_VERSION"), "+", env!("VERGEN_GIT_SHA"));
+
+/// Compile a metered module with the given limits to an artifact, which can be saved and later
+/// run with `run_precompiled` without compiling it again. The module is checked and compiled
+/// with the given options like by `compile`, but always metered.
+/// The artifact starts with a header of the magic bytes, the version of wark, the cost limit, the
+/// memory limit, the compiler and whether NaNs are canonicalized, followed by the serialized
+/// wasmer module. It only loads in the same version of wark on the same target.
+pub fn precompile(
+    wasm: &[u8],
+    budget: u64,
+    mem: u32,
+    options: CompileOptions,
+) -> Result<Vec<u8>, RunError> {
+    let options = CompileOptions {
+        metered: true,
+        ..options
+    };
+    let compiled = compile_uncached(wasm, budget, mem, options)?;
+    let artifact = compiled
+        .module
+        .serialize()
+        .map_err(|e| RunError::CompileError(e.to_string()))?;
+
+    let mut bytes = Vec::with_capacity(artifact.len() + 64);
+    bytes.extend_from_slice(PRECOMPILED_MAGIC);
+    bytes.push(PRECOMPILED_VERSION.len() as u8);
+    bytes.extend_from_slice(PRECOMPILED_VERSION.as_bytes());
+    bytes.extend_from_slice(&budget.to_le_bytes());
+    bytes.extend_from_slice(&mem.to_le_bytes());
+    bytes.push(match options.compiler {
+        Compiler::Cranelift => 0,
+        Compiler::Singlepass => 1,
+    });
+    bytes.push(options.canonicalize_nans as u8);
+    bytes.extend_from_slice(&artifact);
+    Ok(bytes)
+}
+
+/// Load a module precompiled by `precompile`, with the limits and options it was compiled with.
+/// The static operation counts of the module are not part of the artifact, so its runs report
+/// no operations.
+pub fn load_precompiled(bytes: &[u8]) -> Result<CompiledModule, RunError> {
+    let invalid =
+        |reason: &str| RunError::CompileError(format!("Invalid precompiled module: {}", reason));
+
+    let rest = bytes
+        .strip_prefix(PRECOMPILED_MAGIC)
+        .ok_or_else(|| invalid("not a precompiled module"))?;
+    let (&version_len, rest) = rest
+        .split_first()
+        .ok_or_else(|| invalid("truncated header"))?;
+    if rest.len() < version_len as usize + 14 {
+        return Err(invalid("truncated header"));
+    }
+    let (version, rest) = rest.split_at(version_len as usize);
+    if version != PRECOMPILED_VERSION.as_bytes() {
+        return Err(invalid(&format!(
+            "compiled by wark {}, expected {}",
+            String::from_utf8_lossy(version),
+            PRECOMPILED_VERSION
+        )));
+    }
+    let (budget, rest) = rest.split_at(8);
+    let (mem, rest) = rest.split_at(4);
+    let (options, artifact) = rest.split_at(2);
+    let budget = u64::from_le_bytes(budget.try_into().unwrap());
+    let mem = u32::from_le_bytes(mem.try_into().unwrap());
+    let compiler = match options[0] {
+        0 => Compiler::Cranelift,
+        1 => Compiler::Singlepass,
+        _ => return Err(invalid("unknown compiler")),
+    };
+    let options = CompileOptions {
+        metered: true,
+        compiler,
+        canonicalize_nans: options[1] != 0,
+        strict: false,
+    };
+
+    let metering = Arc::new(Cost::new(budget, DEFAULT_EXPORT_PREFIX));
+    let engine = engine(&metering, mem, options);
+
+    // SAFETY: wasmer checks the header of the artifact, including its target and version, but
+    // not its code, so only artifacts written by `precompile` must be loaded.
+    let module = unsafe { Module::deserialize(&engine, artifact.to_vec()) }
+        .map_err(|e| invalid(&e.to_string()))?;
+
+    Ok(CompiledModule {
+        engine,
+        module,
+        metering,
+        budget,
+        mem,
+        metered: true,
+        random_mode: RandomMode::System,
+        clock_mode: ClockMode::System,
+    })
+}
+
+/// Load a module precompiled by `precompile` and run it with the given stdin.
+pub fn run_precompiled(bytes: &[u8], input: String) -> Result<RunResult, RunError> {
+    run_module(&load_precompiled(bytes)?, input)
+}
+
+/// Compile and run a module. Its limits are checked against the configured maxima first.
+pub fn run(request: RunRequest) -> Result<RunResult, RunError> {
+    run_with_permit(request, ())
+}
+
+/// Compile and run a module like `run`, holding the given permit until the module stops running.
+/// A run given up on after its timeout goes on in the background, and keeps the permit until it
+/// really ends.
+pub fn run_with_permit<P: Send + 'static>(
+    request: RunRequest,
+    permit: P,
+) -> Result<RunResult, RunError> {
+    request.validate(config().max_cost, config().max_memory)?;
+
+    let options = CompileOptions {
+        metered: request.metering,
+        compiler: request.compiler,
+        canonicalize_nans: request.canonicalize_nans,
+        strict: request.strict,
+    };
+    let compiled = compile(&request.wasm, request.budget, request.mem, options)?
+        .with_random_mode(request.random_mode)
+        .with_clock_mode(request.clock_mode);
+    match request.timeout {
+        Some(timeout) => {
+            run_module_timeout(Arc::new(compiled), request.input, timeout, None, permit)
+        }
+        None => run_module(&compiled, request.input),
+    }
+}
+
+/// Compile the module once with the given limits and options and run it with each of the inputs
+/// in turn, returning the results in the order of the inputs.
+/// Every run gets its own store and instance, so it starts with a fresh memory, the full cost
+/// limit and fresh WASI state, and nothing a run does is visible to the next ones. If the module
+/// fails to compile, every input gets the compile error.
+/// Each run is given up on after the timeout, if any, as in `run_module_timeout`, and the permit
+/// is held until all of them stop running. A run given up on goes on in the background, so the
+/// inputs after it are not run, and get its time limit error too, instead of running alongside
+/// it under the same permit.
+pub fn run_batch<P: Send + Sync + 'static>(
+    wasm: &[u8],
+    budget: u64,
+    mem: u32,
+    options: CompileOptions,
+    inputs: Vec<String>,
+    timeout: Option<Duration>,
+    permit: P,
+) -> Vec<Result<RunResult, RunError>> {
+    let compiled = match compile(wasm, budget, mem, options) {
+        Ok(compiled) => Arc::new(compiled),
+        Err(e) => return inputs.iter().map(|_| Err(e.clone())).collect(),
+    };
+
+    let permit = Arc::new(permit);
+    let count = inputs.len();
+    let mut results = Vec::with_capacity(count);
+    for input in inputs {
+        let result = match timeout {
+            Some(timeout) => {
+                run_module_timeout(compiled.clone(), input, timeout, None, permit.clone())
+            }
+            None => run_module(&compiled, input),
+        };
+        if let Err(error @ RunError::TimeLimitExceeded(_)) = &result {
+            let skipped = count - results.len();
+            results.extend((0..skipped).map(|_| Err(error.clone())));
+            break;
+        }
+        results.push(result);
+    }
+    results
+}
+
+/// Run a compiled module with the given stdin on its own thread, giving up on it if it does not
+/// finish within the timeout. A running instance can not be interrupted, so the thread is left
+/// to finish in the background, which is still bounded by the cost limit of a metered module.
+/// The thread holds the given permit until the module stops running, so a run given up on still
+/// counts against the permits of the caller.
+/// The stdout is streamed to the given sender, if any, as in `run_module_streaming`.
+pub fn run_module_timeout<P: Send + 'static>(
+    compiled: Arc<CompiledModule>,
+    input: String,
+    timeout: Duration,
+    stdout_stream: Option<StdoutStream>,
+    permit: P,
+) -> Result<RunResult, RunError> {
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("wark-run".to_string())
+        .spawn(move || {
+            let _permit = permit;
+            let _ = sender.send(run_module_streaming(&compiled, input, stdout_stream));
+        })
+        .map_err(|e| RunError::IOError(e.to_string()))?;
+
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => result,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            Err(RunError::TimeLimitExceeded(timeout.as_millis() as u64))
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            Err(RunError::RuntimeError("Run thread panicked".to_string()))
+        }
+    }
+}
 
-    let mut store = Store::new(engine);
-    let module = Module::new(&store, wasm).map_err(|e| RunError::CompileError(e.to_string()))?;
+/// Run a compiled module with the given stdin.
+pub fn run_module(compiled: &CompiledModule, input: String) -> Result<RunResult, RunError> {
+    run_module_streaming(compiled, input, None)
+}
+
+/// Run a solution and a checker at the same time, each on its own thread, with the stdout of each
+/// one piped to the stdin of the other. The checker first reads the byte length of the input on
+/// a line, followed by the input, then everything the solution writes. Neither stdout is
+/// collected, the checker reports its verdict on its stderr.
+/// Either program may wait for the other forever, so both are given up on if they do not finish
+/// within the timeout, like in `run_module_timeout`. A blocked read costs nothing, so both pipes
+/// are closed then, for the reads to end and the programs to stop. The permit is held until both
+/// of them stop running. Returns the results of the solution and of the checker.
+pub fn run_interactive<P: Send + Sync + 'static>(
+    solution: Arc<CompiledModule>,
+    checker: Arc<CompiledModule>,
+    input: &str,
+    timeout: Duration,
+    permit: P,
+) -> (Result<RunResult, RunError>, Result<RunResult, RunError>) {
+    let (mut to_checker, checker_stdin) = Pipe::channel();
+    let (to_solution, solution_stdin) = Pipe::channel();
+    if let Err(e) = write!(to_checker, "{}\n{}", input.len(), input) {
+        let error = RunError::IOError(e.to_string());
+        return (Err(error.clone()), Err(error));
+    }
+
+    // The ends written by the programs, to close them when the programs are given up on
+    let mut write_ends = [to_checker.clone(), to_solution.clone()];
+
+    let deadline = Instant::now() + timeout;
+    let permit = Arc::new(permit);
+    let (sender, receiver) = mpsc::channel();
+    let programs = [
+        (solution, solution_stdin, to_checker),
+        (checker, checker_stdin, to_solution),
+    ];
+    for (index, (compiled, stdin, stdout)) in programs.into_iter().enumerate() {
+        let sender = sender.clone();
+        let permit = permit.clone();
+        let spawned = thread::Builder::new()
+            .name("wark-interactive".to_string())
+            .spawn(move || {
+                let _permit = permit;
+                let stdio = Stdio::Piped { stdin, stdout };
+                let result = catch_panic(|| run_module_unchecked(&compiled, stdio));
+                let _ = sender.send((index, result));
+            });
+        if let Err(e) = spawned {
+            let error = RunError::IOError(e.to_string());
+            return (Err(error.clone()), Err(error));
+        }
+    }
+
+    let mut results = [None, None];
+    for _ in 0..results.len() {
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, result)) => results[index] = Some(result),
+            Err(_) => {
+                for pipe in &mut write_ends {
+                    pipe.close();
+                }
+                break;
+            }
+        }
+    }
+
+    let [solution, checker] = results.map(|result| {
+        result.unwrap_or(Err(RunError::TimeLimitExceeded(timeout.as_millis() as u64)))
+    });
+    (solution, checker)
+}
 
-    // Prepare the standard IO pipes
-    let (mut stdin_sender, stdin_reader) = Pipe::channel();
-    let (stdout_sender, mut stdout_reader) = Pipe::channel();
+/// The sending half of a channel receiving the stdout of a program in chunks, as it is written.
+pub type StdoutStream = UnboundedSender<Vec<u8>>;
+
+/// How the stdout of a program is collected, either read at once after the run, or read by a
+/// thread while the program runs and forwarded to a stream, or not at all when it is piped to
+/// another program.
+enum StdoutReader {
+    Buffered(Pipe),
+    Streamed(JoinHandle<Vec<u8>>),
+    Piped,
+}
+
+/// How the stdin and stdout of a program are connected.
+enum Stdio {
+    /// The input is written to the stdin before the run, and the stdout is collected, streamed to
+    /// the sender if any.
+    Collected {
+        input: String,
+        stdout_stream: Option<StdoutStream>,
+    },
+    /// The stdin and stdout are ends of pipes connected to another program.
+    Piped { stdin: Pipe, stdout: Pipe },
+}
+
+/// Run a compiled module with the given stdin, sending its stdout to the stream while it runs.
+/// The full stdout is still collected into the result. The stream is closed when the run ends.
+pub fn run_module_streaming(
+    compiled: &CompiledModule,
+    input: String,
+    stdout_stream: Option<StdoutStream>,
+) -> Result<RunResult, RunError> {
+    let stdio = Stdio::Collected {
+        input,
+        stdout_stream,
+    };
+    catch_panic(|| run_module_unchecked(compiled, stdio))
+}
+
+/// Run `f`, turning a panic into a runtime error, so a module running into a bug of the runtime
+/// only fails its own run, instead of the task or the request running it.
+fn catch_panic<T>(f: impl FnOnce() -> Result<T, RunError>) -> Result<T, RunError> {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| Err(RunError::internal_panic()))
+}
+
+fn run_module_unchecked(compiled: &CompiledModule, stdio: Stdio) -> Result<RunResult, RunError> {
+    let budget = compiled.budget;
+    let mem = compiled.mem;
+    let metering = &compiled.metering;
+    let module = &compiled.module;
+
+    let mut store = Store::new(compiled.engine.clone());
+
+    // Prepare the standard IO pipes, or take the pipes connected to another program
+    let (stdin_reader, stdout_sender, mut stdin_sender, stdout_reader) = match stdio {
+        Stdio::Collected {
+            input,
+            stdout_stream,
+        } => {
+            let (stdin_sender, stdin_reader) = Pipe::channel();
+            let (stdout_sender, stdout_reader) = Pipe::channel();
+            let stdout_reader = collect_stdout(stdout_reader, stdout_stream)?;
+            (
+                stdin_reader,
+                stdout_sender,
+                Some((stdin_sender, input)),
+                stdout_reader,
+            )
+        }
+        Stdio::Piped { stdin, stdout } => (stdin, stdout, None, StdoutReader::Piped),
+    };
     let (stderr_sender, mut stderr_reader) = Pipe::channel();
 
     // Prepare the WASI sandbox environment
-    let mut sandbox = WasiEnv::builder("app")
+    let mut sandbox = WasiEnv::builder(PROGRAM_NAME)
         .stdin(Box::new(stdin_reader))
         .stdout(Box::new(stdout_sender))
         .stderr(Box::new(stderr_sender))
         .finalize(&mut store)
         .map_err(|e| RunError::CompileError(e.to_string()))?;
 
-    let imports = sandbox
-        .import_object(&mut store, &module)
-        .map_err(|e| RunError::CompileError(e.to_string()))?;
-    let instance = Instance::new(&mut store, &module, &imports)
+    let mut imports = sandbox
+        .import_object(&mut store, module)
         .map_err(|e| RunError::CompileError(e.to_string()))?;
+    let args = DeterministicArgs::new(&mut store, &mut imports);
+    let random = match compiled.random_mode {
+        RandomMode::Deterministic { seed } => {
+            Some(DeterministicRandom::new(&mut store, &mut imports, seed))
+        }
+        RandomMode::System => None,
+    };
+    let clock = Clock::new(&mut store, &mut imports, compiled.clock_mode);
+    let instance = Instance::new(&mut store, module, &imports).map_err(|e| {
+        if e.to_string().contains(MEMORY_LIMIT_ERROR) {
+            RunError::MemoryLimitExceeded(mem)
+        } else {
+            RunError::CompileError(e.to_string())
+        }
+    })?;
+
+    if let Ok(memory) = instance.exports.get_memory("memory") {
+        args.initialize(&mut store, memory.clone());
+        if let Some(random) = &random {
+            random.initialize(&mut store, memory.clone());
+        }
+        clock.initialize(&mut store, memory.clone());
+    }
 
     sandbox
         .initialize(&mut store, instance.clone())
         .map_err(|e| RunError::CompileError(e.to_string()))?;
 
     // Write to the stdin
-    writeln!(stdin_sender, "{}", input).map_err(|e| RunError::IOError(e.to_string()))?;
+    if let Some((stdin_sender, input)) = &mut stdin_sender {
+        writeln!(stdin_sender, "{}", input).map_err(|e| RunError::IOError(e.to_string()))?;
+    }
 
     // Run the program
     let start = instance
         .exports
         .get_function("_start")
         .map_err(|e| RunError::CompileError(e.to_string()))?;
-    match start.call(&mut store, &[]) {
-        Ok(_) => {}
+    let started = Instant::now();
+    let called = start.call(&mut store, &[]);
+    let exec_time = started.elapsed();
+    let exit_code = match called {
+        Ok(_) => 0,
         Err(e) => {
             if let Some(trap) = e.clone().to_trap() {
+                // A `memory.grow` beyond the limit fails instead of trapping, so a program whose
+                // allocation failed traps right after, accessing the memory it did not get or
+                // aborting. A metered module counts its failed grows, otherwise only an access out
+                // of its memory filled up to the limit is told apart. Other traps keep their own
+                // verdict, the program may have handled the failed grow.
+                let spent = compiled.metered
+                    && matches!(
+                        get_remaining_points(&mut store, &instance, metering.prefix()),
+                        CostPoints::Exhausted
+                    );
+                let out_of_memory = if compiled.metered {
+                    matches!(
+                        trap,
+                        TrapCode::HeapAccessOutOfBounds | TrapCode::UnreachableCodeReached
+                    ) && get_failed_memory_grows(&mut store, &instance, metering.prefix()) > 0
+                } else {
+                    matches!(trap, TrapCode::HeapAccessOutOfBounds)
+                        && memory_at_limit(&store, &instance, mem)
+                };
+                if !spent && out_of_memory {
+                    return Err(RunError::MemoryLimitExceeded(mem));
+                }
+
                 match trap {
                     TrapCode::StackOverflow => {
                         return Err(RunError::RuntimeError("Stack overflow".to_string()))
@@ -118,16 +919,18 @@ pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunR
                         ))
                     }
                     TrapCode::UnreachableCodeReached => {
-                        let remaining_budget = get_remaining_points(&mut store, &instance);
+                        let remaining_budget = compiled.metered.then(|| {
+                            get_remaining_points(&mut store, &instance, metering.prefix())
+                        });
                         match remaining_budget {
-                            CostPoints::Remaining(_) => {
+                            Some(CostPoints::Exhausted) => {
+                                return Err(RunError::SpendingLimitExceeded(budget))
+                            }
+                            _ => {
                                 return Err(RunError::RuntimeError(
                                     "Unreachable code reached.".to_string(),
                                 ))
                             }
-                            CostPoints::Exhausted => {
-                                return Err(RunError::SpendingLimitExceeded(budget))
-                            }
                         };
                     }
                     TrapCode::UnalignedAtomic => {
@@ -144,19 +947,10 @@ pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunR
                             wasmer_wasix_types::wasi::Errno::Toobig => {
                                 return Err(RunError::MemoryLimitExceeded(mem));
                             }
-                            _ => {
-                                return Err(RunError::RuntimeError(format!(
-                                    "Exited with errno {}",
-                                    errno
-                                )));
-                            }
+                            // The program exited with `proc_exit`, its result is still collected
+                            _ => errno as i32,
                         },
-                        ExitCode::Other(code) => {
-                            return Err(RunError::RuntimeError(format!(
-                                "Exited with code [{}]",
-                                code
-                            )));
-                        }
+                        ExitCode::Other(code) => code,
                     },
                     WasiError::UnknownWasiVersion => {
                         return Err(RunError::RuntimeError("Unknown WASI version".to_string()));
@@ -167,14 +961,18 @@ pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunR
                 }
             }
         }
-    }
+    };
     sandbox.cleanup(&mut store, None);
 
     // Check the instruction count
-    let remaining_budget = get_remaining_points(&mut store, &instance);
-    let cost = match remaining_budget {
-        CostPoints::Remaining(remaining) => budget - remaining,
-        CostPoints::Exhausted => unreachable!(),
+    let cost = if compiled.metered {
+        let remaining_budget = get_remaining_points(&mut store, &instance, metering.prefix());
+        match remaining_budget {
+            CostPoints::Remaining(remaining) => budget - remaining,
+            CostPoints::Exhausted => unreachable!(),
+        }
+    } else {
+        0
     };
 
     // Check the memory usage
@@ -186,17 +984,24 @@ pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunR
         .collect();
     let memory = memories.pop().unwrap().ty(&store);
     let max_mem = (memory.minimum.0 + 15) / 16;
+    let memory_bytes = memory.minimum.bytes().0 as u64;
     if max_mem > mem {
-        unreachable!();
+        return Err(RunError::MemoryLimitExceeded(mem));
     }
 
     // Read the stdout and stderr
-    let stdout = {
-        let mut buf = Vec::new();
-        stdout_reader
-            .read_to_end(&mut buf)
-            .map_err(|e| RunError::IOError(e.to_string()))?;
-        buf
+    let stdout = match stdout_reader {
+        StdoutReader::Buffered(mut reader) => {
+            let mut buf = Vec::new();
+            reader
+                .read_to_end(&mut buf)
+                .map_err(|e| RunError::IOError(e.to_string()))?;
+            buf
+        }
+        StdoutReader::Streamed(handle) => handle
+            .join()
+            .map_err(|_| RunError::IOError("Failed to read stdout".to_string()))?,
+        StdoutReader::Piped => Vec::new(),
     };
     let stderr = {
         let mut buf = Vec::new();
@@ -207,12 +1012,347 @@ pub fn run(wasm: Box<[u8]>, budget: u64, mem: u32, input: String) -> Result<RunR
     };
 
     let operations = metering.operation_counts.lock().unwrap().clone();
+    let operation_categories = categorize_operations(&operations);
+    let cost_by_operator = metering.operation_costs.lock().unwrap().clone();
+    let function_costs = get_function_costs(&mut store, &instance, metering.prefix());
+    let memory_grows = if compiled.metered {
+        get_memory_grows(&mut store, &instance, metering.prefix())
+    } else {
+        0
+    };
+    let function_names = metering.function_names.lock().unwrap().clone();
 
     Ok(RunResult {
+        exit_code,
         cost,
         memory: max_mem,
+        memory_bytes,
+        memory_grows,
+        exec_time,
         stdout,
         stderr,
         operations,
+        operation_categories,
+        cost_by_operator,
+        function_costs,
+        function_names,
+        verdict: None,
     })
 }
+
+/// Collect the stdout of a program, forwarding it to the stream while the program runs, if any.
+fn collect_stdout(
+    reader: Pipe,
+    stdout_stream: Option<StdoutStream>,
+) -> Result<StdoutReader, RunError> {
+    match stdout_stream {
+        Some(stream) => {
+            let mut reader = reader;
+            let handle = thread::Builder::new()
+                .name("wark-stdout".to_string())
+                .spawn(move || {
+                    let mut stdout = Vec::new();
+                    let mut chunk = [0u8; 4096];
+                    while let Ok(n @ 1..) = reader.read(&mut chunk) {
+                        stdout.extend_from_slice(&chunk[..n]);
+                        let _ = stream.send(chunk[..n].to_vec());
+                    }
+                    stdout
+                })
+                .map_err(|e| RunError::IOError(e.to_string()))?;
+            Ok(StdoutReader::Streamed(handle))
+        }
+        None => Ok(StdoutReader::Buffered(reader)),
+    }
+}
+
+/// Whether the memory of the instance has grown to the memory limit, in MB.
+fn memory_at_limit(store: &Store, instance: &Instance, mem: u32) -> bool {
+    match instance.exports.get_memory("memory") {
+        Ok(memory) => memory.view(store).size() >= Pages(mem * 16),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A request running the WAT module metered, with a 1 MB memory limit and an empty stdin.
+    /// The module must import a WASI function for its WASI version to be known.
+    fn request(wat: &str) -> RunRequest {
+        RunRequest {
+            wasm: wat::parse_str(wat).unwrap().into_boxed_slice(),
+            budget: 1_000_000_000,
+            mem: 1,
+            input: String::new(),
+            metering: true,
+            timeout: None,
+            random_mode: RandomMode::System,
+            clock_mode: ClockMode::System,
+            compiler: Compiler::default(),
+            canonicalize_nans: true,
+            strict: false,
+        }
+    }
+
+    #[test]
+    fn failed_memory_grow_is_memory_limit_exceeded() {
+        let result = run(request(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (loop (br_if 0 (i32.ne (memory.grow (i32.const 1)) (i32.const -1))))
+                    unreachable))
+            "#,
+        ));
+        assert!(
+            matches!(result, Err(RunError::MemoryLimitExceeded(1))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn other_trap_after_failed_memory_grow_is_runtime_error() {
+        let result = run(request(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (drop (memory.grow (i32.const 100)))
+                    (drop (i32.div_s (i32.const 1) (i32.const 0)))))
+            "#,
+        ));
+        assert!(
+            matches!(&result, Err(RunError::RuntimeError(message)) if message == "Integer division by zero"),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn trap_at_memory_limit_is_runtime_error() {
+        let result = run(request(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (memory (export "memory") 16)
+                (func (export "_start") unreachable))
+            "#,
+        ));
+        assert!(
+            matches!(result, Err(RunError::RuntimeError(_))),
+            "{:?}",
+            result
+        );
+    }
+
+    #[test]
+    fn nans_are_canonical() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (func (export "nan") (param i64) (result i64)
+                    (i64.reinterpret_f64
+                        (f64.add (f64.reinterpret_i64 (local.get 0)) (f64.const 0)))))
+            "#,
+        )
+        .unwrap();
+
+        for compiler in [Compiler::Cranelift, Compiler::Singlepass] {
+            let options = CompileOptions {
+                compiler,
+                ..CompileOptions::default()
+            };
+            let compiled = compile_uncached(&wasm, 1_000_000, 1, options).unwrap();
+            let mut store = Store::new(compiled.engine.clone());
+            let instance =
+                Instance::new(&mut store, &compiled.module, &wasmer::imports! {}).unwrap();
+            let nan = instance
+                .exports
+                .get_typed_function::<i64, i64>(&store, "nan")
+                .unwrap();
+
+            // A signaling NaN with a payload, whose bits would otherwise depend on the host
+            for _ in 0..2 {
+                let bits = nan.call(&mut store, 0x7FF0_0000_0000_0123).unwrap();
+                assert_eq!(bits, 0x7FF8_0000_0000_0000, "{:?}", compiler);
+            }
+        }
+    }
+
+    #[test]
+    fn random_state_persists_within_a_run() {
+        // Write two reads of 8 random bytes to stdout
+        let wat = r#"
+            (module
+                (import "wasi_snapshot_preview1" "random_get"
+                    (func $random_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (drop (call $random_get (i32.const 16) (i32.const 8)))
+                    (drop (call $random_get (i32.const 24) (i32.const 8)))
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 16))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+        "#;
+        let stdout = || {
+            let request = RunRequest {
+                random_mode: RandomMode::Deterministic { seed: 1 },
+                ..request(wat)
+            };
+            run(request).unwrap().stdout
+        };
+
+        let first = stdout();
+        assert_eq!(first.len(), 16);
+        assert_ne!(first[..8], first[8..]);
+        // Every run starts from the seed again
+        assert_eq!(stdout(), first);
+    }
+
+    #[test]
+    fn programs_see_only_their_name_and_no_environment() {
+        // Write the argument count and size, then the environment count and size, to stdout
+        let result = run(request(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "args_sizes_get"
+                    (func $args_sizes_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "environ_sizes_get"
+                    (func $environ_sizes_get (param i32 i32) (result i32)))
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (i32.store (i32.const 8) (i32.const -1))
+                    (i32.store (i32.const 12) (i32.const -1))
+                    (drop (call $args_sizes_get (i32.const 0) (i32.const 4)))
+                    (drop (call $environ_sizes_get (i32.const 8) (i32.const 12)))
+                    (i32.store (i32.const 16) (i32.const 0))
+                    (i32.store (i32.const 20) (i32.const 16))
+                    (drop (call $fd_write (i32.const 1) (i32.const 16) (i32.const 1) (i32.const 24)))))
+            "#,
+        ))
+        .unwrap();
+
+        // A single argument, "app" with its NUL byte, and no variables
+        let sizes: Vec<u32> = result
+            .stdout
+            .chunks_exact(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        assert_eq!(sizes, [1, 4, 0, 0]);
+    }
+
+    #[test]
+    fn batch_runs_start_from_a_clean_state() {
+        // Count the runs in memory and in a global, and write both counts as digits to stdout
+        let wat = r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (global $runs (mut i32) (i32.const 0))
+                (func (export "_start")
+                    (i32.store8 (i32.const 100) (i32.add (i32.load8_u (i32.const 100)) (i32.const 1)))
+                    (global.set $runs (i32.add (global.get $runs) (i32.const 1)))
+                    (i32.store8 (i32.const 16) (i32.add (i32.load8_u (i32.const 100)) (i32.const 48)))
+                    (i32.store8 (i32.const 17) (i32.add (global.get $runs) (i32.const 48)))
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 2))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+        "#;
+        let wasm = wat::parse_str(wat).unwrap();
+        let inputs = vec![String::new(), String::new()];
+
+        let results = run_batch(
+            &wasm,
+            1_000_000_000,
+            1,
+            CompileOptions::default(),
+            inputs,
+            None,
+            (),
+        );
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert_eq!(result.unwrap().stdout, b"11");
+        }
+    }
+
+    #[test]
+    fn batch_stops_at_the_first_time_limit_exceeded() {
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "proc_exit" (func (param i32)))
+                (memory (export "memory") 1)
+                (func (export "_start") (loop (br 0))))
+            "#,
+        )
+        .unwrap();
+        let inputs = vec![String::new(), String::new()];
+
+        let results = run_batch(
+            &wasm,
+            1_000_000_000,
+            1,
+            CompileOptions::default(),
+            inputs,
+            Some(Duration::from_millis(100)),
+            (),
+        );
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert!(matches!(result, Err(RunError::TimeLimitExceeded(100))));
+        }
+    }
+
+    #[test]
+    fn interactive_programs_waiting_for_each_other_are_stopped() {
+        // Read the stdin until it ends
+        let wasm = wat::parse_str(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_read"
+                    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const 64))
+                    (loop
+                        (i32.store (i32.const 8) (i32.const 0))
+                        (drop (call $fd_read (i32.const 0) (i32.const 0) (i32.const 1) (i32.const 8)))
+                        (br_if 0 (i32.load (i32.const 8))))))
+            "#,
+        )
+        .unwrap();
+        let compiled =
+            || Arc::new(compile(&wasm, 1_000_000_000, 1, CompileOptions::default()).unwrap());
+
+        let permit = Arc::new(());
+        let (solution, checker) = run_interactive(
+            compiled(),
+            compiled(),
+            "1",
+            Duration::from_millis(100),
+            permit.clone(),
+        );
+        assert!(matches!(solution, Err(RunError::TimeLimitExceeded(100))));
+        assert!(matches!(checker, Err(RunError::TimeLimitExceeded(100))));
+
+        // Both programs stop once their pipes are closed, releasing the permit
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while Arc::strong_count(&permit) > 1 {
+            assert!(Instant::now() < deadline, "the programs are still running");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+}
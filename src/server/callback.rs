@@ -0,0 +1,97 @@
+use super::judge::JudgeResults;
+use crate::config::*;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use rocket::tokio::time::sleep;
+use sha2::Sha256;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The header carrying the signature of a callback body.
+pub const SIGNATURE_HEADER: &str = "X-WARK-Signature";
+
+/// The HTTP client shared by all callbacks, so they reuse pooled connections.
+static CALLBACK_CLIENT: OnceLock<Client> = OnceLock::new();
+
+/// Get the shared callback client, building it on first use.
+/// The timeout covers the whole request, so a receiver that never responds is retried like a
+/// failed one instead of hanging the callback.
+fn callback_client() -> &'static Client {
+    CALLBACK_CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(Duration::from_secs(callback_timeout()))
+            .build()
+            .expect("Failed to build the callback client")
+    })
+}
+
+/// Sign a callback body with HMAC-SHA256 keyed on the application secret,
+/// formatted as `sha256=<lowercase hex digest>`.
+pub fn sign(body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(config().app_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+
+    format!("sha256={:x}", mac.finalize().into_bytes())
+}
+
+/// Send the results of a judge to its callback URL, signed in the `X-WARK-Signature` header.
+/// Failed requests and error responses are retried up to `CALLBACK_ATTEMPTS` attempts in total,
+/// with an exponential backoff from `CALLBACK_RETRY_DELAY` and a random jitter.
+pub async fn send(url: &str, result: &JudgeResults) {
+    let body = match rocket::serde::json::to_string(result) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Error serializing callback. {} ({})", e, url);
+            return;
+        }
+    };
+    let signature = sign(body.as_bytes());
+
+    let client = callback_client();
+    let attempts = callback_attempts();
+    let mut delay = callback_retry_delay();
+
+    for attempt in 1..=attempts {
+        let req = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone());
+
+        let error = match req.send().await {
+            Ok(res) if res.status().is_success() => {
+                info!("Callback sent successfully. {} ({})", res.status(), url);
+                return;
+            }
+            Ok(res) => res.status().to_string(),
+            Err(e) => e.to_string(),
+        };
+
+        if attempt == attempts {
+            warn!(
+                "Error sending callback, giving up after {} attempts. {} ({})",
+                attempts, error, url
+            );
+            return;
+        }
+
+        info!(
+            "Error sending callback, retrying in {} ms. {} ({})",
+            delay, error, url
+        );
+        sleep(Duration::from_millis(delay + jitter(delay))).await;
+        delay = delay.saturating_mul(2);
+    }
+}
+
+/// A pseudo-random jitter of up to a quarter of the delay, so callbacks failing together
+/// are not all retried at the same time.
+fn jitter(delay: u64) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos() as u64)
+        .unwrap_or(0);
+
+    nanos % (delay / 4 + 1)
+}
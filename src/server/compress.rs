@@ -1,5 +1,5 @@
 use async_compression::tokio::bufread::GzipEncoder;
-use rocket::{fairing::AdHoc, http::Header, tokio::io};
+use rocket::{fairing::AdHoc, http::Header, tokio::io, Response};
 use std::io::Cursor;
 
 pub fn fairing() -> AdHoc {
@@ -20,6 +20,11 @@ pub fn fairing() -> AdHoc {
                 return;
             }
 
+            // Check if the response is streamed.
+            if is_streamed(res) {
+                return;
+            }
+
             // Encode the response.
             let compressed: Vec<u8> = {
                 let body = res.body_mut().take();
@@ -42,3 +47,12 @@ pub fn fairing() -> AdHoc {
         })
     })
 }
+
+/// Whether the response is streamed while it is produced, which compressing it whole would hold
+/// back until it ends.
+fn is_streamed(res: &Response<'_>) -> bool {
+    res.content_type().map_or(false, |content_type| {
+        content_type.is_event_stream()
+            || (content_type.top() == "application" && content_type.sub() == "x-ndjson")
+    })
+}
@@ -1,15 +1,24 @@
 use super::compress;
 use super::execute;
+use super::jobs;
 use super::judge;
 use super::jwt;
+use super::metrics;
+use super::shutdown;
+use super::trace;
 use super::version;
 use crate::config::*;
+use crate::permit;
+use rocket::config::{Shutdown, TlsConfig};
 use rocket::data::ByteUnit;
+use rocket::http::Status;
+use rocket::response::status;
 use rocket::serde::{json::Json, Deserialize, Serialize};
 use rocket::Build;
-use rocket::Config;
 use rocket::Rocket;
-use std::net::Ipv4Addr;
+use rocket::State;
+use std::sync::Arc;
+use std::time::Instant;
 
 #[get("/")]
 fn index() -> &'static str {
@@ -23,36 +32,156 @@ pub struct ServerInfo {
     pub commit: String,
     pub data: String,
     pub os: String,
+    pub wasmer_version: String,
+    pub uptime_seconds: u64,
+    pub max_cost: u64,
+    pub max_memory: u32,
 }
 
+/// The time the server started, to report its uptime.
+pub struct StartTime(Instant);
+
 #[get("/info")]
-fn info() -> Json<ServerInfo> {
+fn info(start_time: &State<StartTime>) -> Json<ServerInfo> {
     Json(ServerInfo {
         version: env!("VERGEN_GIT_DESCRIBE").to_string(),
         commit: env!("VERGEN_GIT_SHA").to_string(),
         data: env!("VERGEN_GIT_COMMIT_TIMESTAMP").to_string(),
         os: env!("VERGEN_CARGO_TARGET_TRIPLE").to_string(),
+        wasmer_version: wasmer::VERSION.to_string(),
+        uptime_seconds: start_time.0.elapsed().as_secs(),
+        max_cost: config().max_cost,
+        max_memory: config().max_memory,
     })
 }
 
+/// Check that the server is alive.
+#[get("/healthz")]
+fn healthz() -> &'static str {
+    "OK"
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct Readiness {
+    pub ready: bool,
+    /// The number of modules that can start running right away
+    pub available_runs: usize,
+    /// The number of queued and running jobs
+    pub queued_jobs: usize,
+}
+
+/// Check that the server can accept work right away, responding with 503 if it can not.
+#[get("/readyz")]
+fn readyz(store: &State<Arc<jobs::JobStore>>) -> status::Custom<Json<Readiness>> {
+    let available_runs = permit::available_run_permits();
+    let queued_jobs = store.unfinished();
+    let ready = available_runs > 0 && queued_jobs < config().max_queued_jobs;
+
+    let status = if ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+
+    status::Custom(
+        status,
+        Json(Readiness {
+            ready,
+            available_runs,
+            queued_jobs,
+        }),
+    )
+}
+
+/// Check that the application secret is not the default one, which makes tokens and callback
+/// signatures trivially forgeable. Debug builds only warn, release builds refuse to start unless
+/// `ALLOW_INSECURE_SECRET` is set. Returns whether the server may start.
+pub fn check_app_secret() -> bool {
+    if config().app_secret != DEFAULT_APP_SECRET {
+        return true;
+    }
+
+    if cfg!(debug_assertions) {
+        warn!("APP_SECRET is not set, using the default secret for local testing");
+        return true;
+    }
+
+    if allow_insecure_secret() {
+        error!("APP_SECRET is not set, anyone can forge tokens and callback signatures with the default secret");
+        return true;
+    }
+
+    error!("APP_SECRET is not set, refusing to start with the default secret. Set APP_SECRET, or ALLOW_INSECURE_SECRET=1 to start anyway");
+    false
+}
+
+/// The TLS configuration of the server, if both `TLS_CERT` and `TLS_KEY` are set.
+/// The certificate and key are read once at launch, so the server has to be restarted to pick up
+/// renewed ones.
+fn tls_config() -> Option<TlsConfig> {
+    match (tls_cert(), tls_key()) {
+        (Some(cert), Some(key)) => Some(TlsConfig::from_paths(cert, key)),
+        (None, None) => None,
+        _ => {
+            warn!("Only one of TLS_CERT and TLS_KEY is set, serving plain HTTP");
+            None
+        }
+    }
+}
+
 /// Get the Rocket instance
 pub fn rocket() -> Rocket<Build> {
-    let json_limit: ByteUnit = "10MB".parse().unwrap();
-    let limits = Config::default().limits.limit("json", json_limit);
+    trace::set_panic_hook();
+
+    let body_limit: ByteUnit = "10MB".parse().unwrap();
+    let limits = rocket::Config::default()
+        .limits
+        .limit("json", body_limit)
+        .limit("data-form", body_limit)
+        .limit("bytes", body_limit)
+        .limit("string", body_limit);
 
     let server = rocket::build()
-        .configure(Config {
-            address: Ipv4Addr::new(0, 0, 0, 0).into(),
-            port: server_port(),
+        .configure(rocket::Config {
+            address: bind_address(),
+            tls: tls_config(),
+            port: config().server_port,
             limits,
-            ..Config::default()
+            shutdown: Shutdown {
+                grace: drain_timeout() as u32,
+                ..Shutdown::default()
+            },
+            ..rocket::Config::default()
         })
         .mount(
             "/",
-            routes![index, info, jwt::validate, execute::execute, judge::judge],
-        );
+            routes![
+                index,
+                info,
+                healthz,
+                readyz,
+                jwt::validate,
+                execute::execute,
+                execute::execute_upload,
+                execute::execute_batch,
+                execute::stream,
+                judge::judge,
+                judge::judge_upload,
+                judge::stream,
+                jobs::submit,
+                jobs::poll,
+                metrics::export
+            ],
+        )
+        .manage(Arc::new(jobs::JobStore::new()))
+        .manage(StartTime(Instant::now()));
 
-    let server = server.attach(version::fairing());
+    let server = server
+        .attach(version::fairing())
+        .attach(trace::fairing())
+        .attach(jwt::fairing())
+        .attach(shutdown::fairing());
 
     if cfg!(debug_assertions) {
         server
@@ -1,119 +1,464 @@
+use super::gzip::GzipJson;
 use super::jwt;
+use super::metrics::metrics;
+use super::trace::{self, RequestId};
+use crate::clock::ClockMode;
 use crate::config::*;
+use crate::judger::check_time_limit;
+use crate::permit::acquire_run_permit;
+use crate::random::RandomMode;
 use crate::run;
 use base64::{engine::general_purpose, Engine as _};
+use rocket::form::{Errors, Form};
+use rocket::response::stream::{Event, EventStream};
 use rocket::serde::{
-    json::{Error, Json},
+    json::{self, Error, Json},
     Deserialize, Serialize,
 };
+use rocket::tokio::select;
+use rocket::tokio::sync::mpsc;
 use rocket::tokio::task;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 // Define a struct to represent incoming code submissions
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct Submission {
+    /// The base64-encoded WebAssembly binary, left out of multipart uploads
+    #[serde(default)]
     wasm: String,
     input: String,
     cost: u64,
     memory: u32,
+    /// The wall-clock time limit, in milliseconds (optional)
+    time_limit: Option<u64>,
+    /// The seed of the random bytes of the program, real entropy if not set (optional)
+    seed: Option<u64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct ExecutionResult {
+    /// Whether the program ran to completion and exited with code 0
     success: bool,
+    /// The exit code of the program, if it ran to completion
+    exit_code: Option<i32>,
     cost: Option<u64>,
     memory: Option<u32>,
+    /// The memory usage of the program, in bytes
+    memory_bytes: Option<u64>,
+    /// The number of `memory.grow` operations the program executed
+    memory_grows: Option<u64>,
     stdout: Option<String>,
     stderr: Option<String>,
     message: Option<String>,
+    /// The operation counts of the program, if requested with the `operations` query flag
+    operations: Option<HashMap<String, u64>>,
+}
+
+impl ExecutionResult {
+    fn failure(message: String) -> Self {
+        ExecutionResult {
+            success: false,
+            exit_code: None,
+            cost: None,
+            memory: None,
+            memory_bytes: None,
+            memory_grows: None,
+            stdout: None,
+            stderr: None,
+            message: Some(message),
+            operations: None,
+        }
+    }
+
+    fn from_run(result: Result<run::RunResult, run::RunError>, operations: bool) -> Self {
+        match result {
+            Ok(result) => ExecutionResult {
+                success: result.exit_code == 0,
+                exit_code: Some(result.exit_code),
+                cost: Some(result.cost),
+                memory: Some(result.memory),
+                memory_bytes: Some(result.memory_bytes),
+                memory_grows: Some(result.memory_grows),
+                stdout: Some(String::from_utf8(result.stdout).unwrap_or(
+                    "Failed to decode stdout, it may contain invalid UTF-8".to_string(),
+                )),
+                stderr: Some(String::from_utf8(result.stderr).unwrap_or(
+                    "Failed to decode stderr, it may contain invalid UTF-8".to_string(),
+                )),
+                message: None,
+                operations: operations.then_some(result.operations),
+            },
+            Err(err) => ExecutionResult::failure(err.to_string()),
+        }
+    }
+}
+
+/// Check the limits of a submission.
+fn check_submission(submission: &Submission) -> Result<(), String> {
+    if submission.cost > config().max_cost {
+        return Err("Invalid cost limit".to_string());
+    }
+
+    if submission.memory > config().max_memory {
+        return Err("Invalid memory limit".to_string());
+    }
+
+    check_time_limit(submission.time_limit)
+}
+
+/// Check the limits of a submission and decode its module.
+fn prepare(
+    submission: Result<GzipJson<Submission>, Error<'_>>,
+) -> Result<(Submission, Box<[u8]>), String> {
+    let submission = match submission {
+        Ok(submission) => submission.into_inner(),
+        Err(e) => return Err(format!("Invalid submission. Error parsing JSON: {}", e)),
+    };
+
+    decode_submission(submission)
+}
+
+/// Check the limits of a parsed submission and decode its module.
+fn decode_submission(submission: Submission) -> Result<(Submission, Box<[u8]>), String> {
+    check_submission(&submission)?;
+
+    match general_purpose::STANDARD.decode(submission.wasm.as_bytes()) {
+        Ok(wasm) => Ok((submission, wasm.into_boxed_slice())),
+        Err(_) => Err("Invalid wasm".to_string()),
+    }
+}
+
+/// A submission uploaded as `multipart/form-data`, with the raw module in its own part.
+#[derive(FromForm)]
+pub struct SubmissionUpload<'r> {
+    /// The raw WebAssembly binary
+    wasm: &'r [u8],
+    /// The submission as JSON, without the `wasm` field
+    submission: &'r str,
 }
 
 // Define a Rocket route to handle incoming code submissions
-#[post("/run", format = "json", data = "<submission>")]
+#[post("/run?<operations>", format = "json", data = "<submission>")]
 pub async fn execute(
-    _token: jwt::Token,
-    submission: Result<Json<Submission>, Error<'_>>,
+    _token: jwt::RunToken,
+    request_id: &RequestId,
+    operations: Option<bool>,
+    submission: Result<GzipJson<Submission>, Error<'_>>,
 ) -> Json<ExecutionResult> {
-    let submission = match submission {
-        Ok(submission) => submission.into_inner(),
+    let (submission, wasm) = match prepare(submission) {
+        Ok(prepared) => prepared,
+        Err(e) => return Json(ExecutionResult::failure(e)),
+    };
+
+    let result = run_submission(submission, wasm, operations.unwrap_or(false))
+        .instrument(request_id.span())
+        .await;
+    Json(result)
+}
+
+/// Run a submission uploaded as `multipart/form-data`, like `/run`.
+#[post("/run?<operations>", format = "multipart/form-data", data = "<upload>")]
+pub async fn execute_upload(
+    _token: jwt::RunToken,
+    request_id: &RequestId,
+    operations: Option<bool>,
+    upload: Result<Form<SubmissionUpload<'_>>, Errors<'_>>,
+) -> Json<ExecutionResult> {
+    let upload = match upload {
+        Ok(upload) => upload.into_inner(),
+        Err(e) => {
+            let message = format!("Invalid submission. Error parsing form: {}", e);
+            return Json(ExecutionResult::failure(message));
+        }
+    };
+
+    let submission = match json::from_str::<Submission>(upload.submission) {
+        Ok(submission) => submission,
         Err(e) => {
             let message = format!("Invalid submission. Error parsing JSON: {}", e);
-            return Json(ExecutionResult {
-                success: false,
-                cost: None,
-                memory: None,
-                stdout: None,
-                stderr: None,
-                message: Some(message),
-            });
+            return Json(ExecutionResult::failure(message));
         }
     };
 
-    if submission.cost > max_cost() {
-        return Json(ExecutionResult {
-            success: false,
-            cost: None,
-            memory: None,
-            stdout: None,
-            stderr: None,
-            message: Some("Invalid cost limit".to_string()),
-        });
+    if let Err(e) = check_submission(&submission) {
+        return Json(ExecutionResult::failure(e));
     }
 
-    if submission.memory > max_memory() {
-        return Json(ExecutionResult {
-            success: false,
-            cost: None,
-            memory: None,
-            stdout: None,
-            stderr: None,
-            message: Some("Invalid memory limit".to_string()),
-        });
-    }
+    let wasm = upload.wasm.to_vec().into_boxed_slice();
+    let result = run_submission(submission, wasm, operations.unwrap_or(false))
+        .instrument(request_id.span())
+        .await;
+    Json(result)
+}
 
-    let wasm = match general_purpose::STANDARD.decode(submission.wasm.as_bytes()) {
-        Ok(wasm) => wasm.into_boxed_slice(),
-        Err(_) => {
-            return Json(ExecutionResult {
-                success: false,
-                cost: None,
-                memory: None,
-                stdout: None,
-                stderr: None,
-                message: Some("Invalid wasm".to_string()),
-            })
+/// Run a batch of independent submissions, each limited by the concurrency limit of the server.
+/// The results are in the order of the submissions, and an invalid or failing submission only
+/// fails its own result.
+#[post("/run/batch?<operations>", format = "json", data = "<submissions>")]
+pub async fn execute_batch(
+    _token: jwt::RunToken,
+    request_id: &RequestId,
+    operations: Option<bool>,
+    submissions: Result<GzipJson<Vec<Submission>>, Error<'_>>,
+) -> Json<Vec<ExecutionResult>> {
+    let submissions = match submissions {
+        Ok(submissions) => submissions.into_inner(),
+        Err(e) => {
+            let message = format!("Invalid submission. Error parsing JSON: {}", e);
+            return Json(vec![ExecutionResult::failure(message)]);
         }
     };
 
-    let handle = task::spawn_blocking(move || {
-        run::run(wasm, submission.cost, submission.memory, submission.input)
+    if submissions.len() > config().max_batch_size {
+        let message = format!(
+            "Invalid batch, got {} submissions, max is {}",
+            submissions.len(),
+            config().max_batch_size
+        );
+        return Json(vec![ExecutionResult::failure(message)]);
+    }
+
+    let span = request_id.span();
+    let mut tasks = Vec::new();
+
+    for submission in submissions {
+        let task = task::spawn(
+            async move {
+                match decode_submission(submission) {
+                    Ok((submission, wasm)) => {
+                        run_submission(submission, wasm, operations.unwrap_or(false)).await
+                    }
+                    Err(e) => ExecutionResult::failure(e),
+                }
+            }
+            .instrument(span.clone()),
+        );
+        tasks.push(task);
+    }
+
+    let mut results = Vec::new();
+
+    for task in tasks {
+        results.push(match task.await {
+            Ok(result) => result,
+            Err(e) => ExecutionResult::failure(format!("Submission failed: {}", e)),
+        });
+    }
+
+    Json(results)
+}
+
+/// Run a checked submission with its decoded module.
+async fn run_submission(
+    submission: Submission,
+    wasm: Box<[u8]>,
+    operations: bool,
+) -> ExecutionResult {
+    let permit = acquire_run_permit().await;
+    let started = Instant::now();
+    let handle = trace::spawn_blocking(move || {
+        let request = run::RunRequest {
+            wasm,
+            budget: submission.cost,
+            mem: submission.memory,
+            input: submission.input,
+            metering: true,
+            timeout: submission.time_limit.map(Duration::from_millis),
+            random_mode: RandomMode::from_seed(submission.seed),
+            clock_mode: ClockMode::System,
+            compiler: config().compiler,
+            canonicalize_nans: config().canonicalize_nans,
+            strict: config().strict,
+        };
+        run::run_with_permit(request, permit)
     });
 
-    let result = handle.await.unwrap();
+    let result = handle
+        .await
+        .unwrap_or_else(|_| Err(run::RunError::internal_panic()));
+    metrics().record_run("run", &result, started.elapsed());
 
-    match result {
-        Ok(result) => {
-            Json(ExecutionResult {
-                success: true,
-                cost: Some(result.cost),
-                memory: Some(result.memory),
-                stdout: Some(String::from_utf8(result.stdout).unwrap_or(
-                    "Failed to decode stdout, it may contain invalid UTF-8".to_string(),
-                )),
-                stderr: Some(String::from_utf8(result.stderr).unwrap_or(
-                    "Failed to decode stderr, it may contain invalid UTF-8".to_string(),
-                )),
-                message: None,
-            })
+    ExecutionResult::from_run(result, operations)
+}
+
+enum StreamEvent {
+    Stdout(Option<Vec<u8>>),
+    Done(Box<Result<run::RunResult, run::RunError>>),
+}
+
+/// Run a submission like `/run`, streaming its stdout as `stdout` events while it runs, followed
+/// by a `result` event with the execution result, whose stdout is left out.
+#[post("/run/stream?<operations>", format = "json", data = "<submission>")]
+pub async fn stream(
+    _token: jwt::RunToken,
+    request_id: &RequestId,
+    operations: Option<bool>,
+    submission: Result<GzipJson<Submission>, Error<'_>>,
+) -> EventStream![] {
+    let prepared = prepare(submission);
+    let options = config().compile_options();
+    let span = request_id.span();
+
+    EventStream! {
+        match prepared {
+            Err(e) => {
+                yield Event::json(&ExecutionResult::failure(e)).event("result");
+            }
+            Ok((submission, wasm)) => {
+                let permit = acquire_run_permit().await;
+                let started = Instant::now();
+                let (sender, mut receiver) = mpsc::unbounded_channel();
+                let mut handle = task::spawn_blocking(move || {
+                    let _span = span.entered();
+                    let compiled = run::compile(
+                        &wasm,
+                        submission.cost,
+                        submission.memory,
+                        options,
+                    )?
+                        .with_random_mode(RandomMode::from_seed(submission.seed));
+                    match submission.time_limit {
+                        Some(time_limit) => run::run_module_timeout(
+                            Arc::new(compiled),
+                            submission.input,
+                            Duration::from_millis(time_limit),
+                            Some(sender),
+                            permit,
+                        ),
+                        None => run::run_module_streaming(&compiled, submission.input, Some(sender)),
+                    }
+                });
+
+                let mut decoder = Utf8Chunks::default();
+                let result = loop {
+                    let next = select! {
+                        biased;
+                        chunk = receiver.recv() => StreamEvent::Stdout(chunk),
+                        result = &mut handle => StreamEvent::Done(Box::new(
+                            result.unwrap_or_else(|_| Err(run::RunError::internal_panic()))
+                        )),
+                    };
+
+                    match next {
+                        StreamEvent::Stdout(Some(chunk)) => {
+                            yield Event::data(decoder.decode(&chunk)).event("stdout");
+                        }
+                        StreamEvent::Stdout(None) => break handle
+                            .await
+                            .unwrap_or_else(|_| Err(run::RunError::internal_panic())),
+                        StreamEvent::Done(result) => break *result,
+                    }
+                };
+
+                // Flush the chunks received after the run ended
+                while let Ok(chunk) = receiver.try_recv() {
+                    yield Event::data(decoder.decode(&chunk)).event("stdout");
+                }
+
+                metrics().record_run("stream", &result, started.elapsed());
+                let mut result = ExecutionResult::from_run(result, operations.unwrap_or(false));
+                result.stdout = None;
+                yield Event::json(&result).event("result");
+            }
         }
-        Err(err) => Json(ExecutionResult {
-            success: false,
-            cost: None,
-            memory: None,
-            stdout: None,
-            stderr: None,
-            message: Some(format!("{:?}", err)),
-        }),
+    }
+}
+
+/// Decode chunks of UTF-8 text, carrying a character split between chunks over to the next one.
+#[derive(Default)]
+struct Utf8Chunks {
+    pending: Vec<u8>,
+}
+
+impl Utf8Chunks {
+    fn decode(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+
+        let valid = match std::str::from_utf8(&self.pending) {
+            Ok(_) => self.pending.len(),
+            // Keep an incomplete character at the end for the next chunk
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.pending.len(),
+        };
+
+        let rest = self.pending.split_off(valid);
+        let text = String::from_utf8_lossy(&self.pending).into_owned();
+        self.pending = rest;
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::core::rocket;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::json;
+
+    /// A module printing "hello" to stdout.
+    const HELLO: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "fd_write"
+                (func $fd_write (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (data (i32.const 16) "hello")
+            (func (export "_start")
+                (i32.store (i32.const 0) (i32.const 16))
+                (i32.store (i32.const 4) (i32.const 5))
+                (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+    "#;
+
+    /// A module exiting with code 3.
+    const EXIT_3: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+            (memory (export "memory") 1)
+            (func (export "_start")
+                (call $proc_exit (i32.const 3))))
+    "#;
+
+    /// Run the WAT module through `/run`.
+    async fn post_run(wat: &str) -> ExecutionResult {
+        let client = Client::tracked(rocket()).await.unwrap();
+        let submission = json!({
+            "wasm": general_purpose::STANDARD.encode(wat::parse_str(wat).unwrap()),
+            "input": "",
+            "cost": 1_000_000,
+            "memory": 1,
+        });
+
+        let response = client
+            .post("/run")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", jwt::test_token()),
+            ))
+            .body(submission.to_string())
+            .dispatch()
+            .await;
+        response.into_json().await.unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn run_returns_the_output() {
+        let result = post_run(HELLO).await;
+
+        assert!(result.success, "{:?}", result);
+        assert_eq!(result.stdout.as_deref(), Some("hello"));
+    }
+
+    #[rocket::async_test]
+    async fn run_reports_the_exit_code() {
+        let result = post_run(EXIT_3).await;
+
+        assert_eq!(result.exit_code, Some(3), "{:?}", result);
+        assert!(!result.success);
     }
 }
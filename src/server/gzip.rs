@@ -0,0 +1,82 @@
+use async_compression::tokio::bufread::GzipDecoder;
+use rocket::data::{self, Data, FromData, Limits};
+use rocket::http::Status;
+use rocket::request::{local_cache, Request};
+use rocket::serde::json::{self, Error};
+use rocket::tokio::io::AsyncReadExt;
+use serde::de::DeserializeOwned;
+use std::io;
+
+/// A JSON request body like `Json`, which may also be compressed with `Content-Encoding: gzip`.
+/// Both the compressed and the decompressed body are bounded by the `json` limit.
+pub struct GzipJson<T>(pub T);
+
+impl<T> GzipJson<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+fn too_large() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "data limit exceeded")
+}
+
+/// Read the body, decompressing it if it is gzip encoded.
+async fn read_body(req: &Request<'_>, data: Data<'_>) -> Result<String, (Status, io::Error)> {
+    let limit = req.limits().get("json").unwrap_or(Limits::JSON);
+    let gzip = req
+        .headers()
+        .get_one("Content-Encoding")
+        .map(|encoding| encoding.trim().eq_ignore_ascii_case("gzip"))
+        .unwrap_or(false);
+
+    let body = data
+        .open(limit)
+        .into_bytes()
+        .await
+        .map_err(|e| (Status::BadRequest, e))?;
+    if !body.is_complete() {
+        return Err((Status::PayloadTooLarge, too_large()));
+    }
+    let body = body.into_inner();
+
+    let body = if gzip {
+        let mut decompressed = Vec::new();
+        GzipDecoder::new(&body[..])
+            .take(limit.as_u64() + 1)
+            .read_to_end(&mut decompressed)
+            .await
+            .map_err(|e| (Status::BadRequest, e))?;
+        if decompressed.len() as u64 > limit.as_u64() {
+            return Err((Status::PayloadTooLarge, too_large()));
+        }
+        decompressed
+    } else {
+        body
+    };
+
+    String::from_utf8(body).map_err(|e| {
+        (
+            Status::BadRequest,
+            io::Error::new(io::ErrorKind::InvalidData, e),
+        )
+    })
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned> FromData<'r> for GzipJson<T> {
+    type Error = Error<'r>;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let body = match read_body(req, data).await {
+            Ok(body) => body,
+            Err((status, e)) => return data::Outcome::Failure((status, Error::Io(e))),
+        };
+
+        let body: &'r str = local_cache!(req, body);
+        match json::from_str(body) {
+            Ok(value) => data::Outcome::Success(GzipJson(value)),
+            Err(e) => data::Outcome::Failure((Status::UnprocessableEntity, Error::Parse(body, e))),
+        }
+    }
+}
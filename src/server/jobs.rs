@@ -0,0 +1,243 @@
+use super::gzip::GzipJson;
+use super::judge::{check_local_files, run_specs, JudgeResults};
+use super::jwt;
+use super::metrics::metrics;
+use super::shutdown;
+use super::trace::{self, RequestId};
+use crate::config::config;
+use crate::judger::JudgeSpec;
+use base64::engine::general_purpose;
+use base64::Engine;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::{
+    json::{Error, Json},
+    Deserialize, Serialize,
+};
+use rocket::State;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobSubmission {
+    /// The base64-encoded WebAssembly binary
+    wasm: String,
+    /// Judge specifications
+    specs: Vec<JudgeSpec>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct JobResponse {
+    id: Option<String>,
+    status: Option<JobStatus>,
+    /// The results of the job, once it is done
+    result: Option<JudgeResults>,
+    error: Option<String>,
+}
+
+struct Job {
+    status: JobStatus,
+    result: Option<JudgeResults>,
+    finished: Option<Instant>,
+}
+
+/// The in-memory store of the submitted jobs.
+/// Finished jobs are evicted once they are older than the `JOB_TTL`.
+pub struct JobStore {
+    jobs: Mutex<HashMap<String, Job>>,
+}
+
+impl JobStore {
+    pub fn new() -> Self {
+        JobStore {
+            jobs: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The number of queued and running jobs.
+    pub fn unfinished(&self) -> usize {
+        count_unfinished(&self.jobs.lock().unwrap())
+    }
+
+    /// Add a queued job, unless the number of unfinished jobs has reached `MAX_QUEUED_JOBS`.
+    fn enqueue(&self) -> Option<String> {
+        let mut jobs = self.jobs.lock().unwrap();
+        evict_expired(&mut jobs);
+
+        let unfinished = count_unfinished(&jobs);
+        if unfinished >= config().max_queued_jobs {
+            return None;
+        }
+
+        metrics().set_queued_jobs(unfinished + 1);
+
+        // A random ID, so the jobs of other clients can not be guessed and polled
+        let id = Uuid::new_v4().to_string();
+        jobs.insert(
+            id.clone(),
+            Job {
+                status: JobStatus::Queued,
+                result: None,
+                finished: None,
+            },
+        );
+
+        Some(id)
+    }
+
+    fn start(&self, id: &str) {
+        if let Some(job) = self.jobs.lock().unwrap().get_mut(id) {
+            job.status = JobStatus::Running;
+        }
+    }
+
+    fn finish(&self, id: &str, result: JudgeResults) {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(job) = jobs.get_mut(id) {
+            job.status = JobStatus::Done;
+            job.result = Some(result);
+            job.finished = Some(Instant::now());
+        }
+
+        metrics().set_queued_jobs(count_unfinished(&jobs));
+    }
+
+    fn get(&self, id: &str) -> Option<(JobStatus, Option<JudgeResults>)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        evict_expired(&mut jobs);
+
+        jobs.get(id).map(|job| (job.status, job.result.clone()))
+    }
+}
+
+impl Default for JobStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn count_unfinished(jobs: &HashMap<String, Job>) -> usize {
+    jobs.values()
+        .filter(|job| job.status != JobStatus::Done)
+        .count()
+}
+
+/// Remove the finished jobs older than the `JOB_TTL`.
+fn evict_expired(jobs: &mut HashMap<String, Job>) {
+    let ttl = Duration::from_secs(config().job_ttl);
+    jobs.retain(|_, job| match job.finished {
+        Some(finished) => finished.elapsed() < ttl,
+        None => true,
+    });
+}
+
+fn job_error(code: Status, message: String) -> status::Custom<Json<JobResponse>> {
+    status::Custom(
+        code,
+        Json(JobResponse {
+            id: None,
+            status: None,
+            result: None,
+            error: Some(message),
+        }),
+    )
+}
+
+/// Enqueue a judge submission and respond with its job ID immediately.
+#[post("/jobs", format = "json", data = "<submission>")]
+pub async fn submit(
+    _token: jwt::JudgeToken,
+    request_id: &RequestId,
+    store: &State<Arc<JobStore>>,
+    submission: Result<GzipJson<JobSubmission>, Error<'_>>,
+) -> status::Custom<Json<JobResponse>> {
+    let submission = match submission {
+        Ok(submission) => submission.into_inner(),
+        Err(e) => {
+            return job_error(
+                Status::BadRequest,
+                format!("Invalid submission. Error parsing JSON: {}", e),
+            )
+        }
+    };
+
+    let wasm = match general_purpose::STANDARD.decode(submission.wasm.as_bytes()) {
+        Ok(wasm) => wasm.into_boxed_slice(),
+        Err(_) => {
+            return job_error(
+                Status::BadRequest,
+                "Invalid submission. Error decoding base64.".to_string(),
+            )
+        }
+    };
+
+    if let Err(e) = check_local_files(&submission.specs) {
+        return job_error(Status::BadRequest, e);
+    }
+
+    let id = match store.enqueue() {
+        Some(id) => id,
+        None => {
+            return job_error(
+                Status::ServiceUnavailable,
+                "Too many queued jobs, try again later".to_string(),
+            )
+        }
+    };
+    let store = store.inner().clone();
+    let job_id = id.clone();
+    let background = shutdown::track();
+    request_id.span().in_scope(|| {
+        info!("Queued job {}", id);
+        trace::spawn(async move {
+            let _background = background;
+            store.start(&job_id);
+            let result = run_specs(wasm, submission.specs).await;
+            store.finish(&job_id, result);
+            info!("Finished job {}", job_id);
+        });
+    });
+
+    status::Custom(
+        Status::Accepted,
+        Json(JobResponse {
+            id: Some(id),
+            status: Some(JobStatus::Queued),
+            result: None,
+            error: None,
+        }),
+    )
+}
+
+/// Poll the status of a job, and its results once it is done.
+#[get("/jobs/<id>")]
+pub fn poll(
+    _token: jwt::JudgeToken,
+    store: &State<Arc<JobStore>>,
+    id: &str,
+) -> status::Custom<Json<JobResponse>> {
+    match store.get(id) {
+        Some((status, result)) => status::Custom(
+            Status::Ok,
+            Json(JobResponse {
+                id: Some(id.to_string()),
+                status: Some(status),
+                result,
+                error: None,
+            }),
+        ),
+        None => job_error(Status::NotFound, format!("Job {} not found", id)),
+    }
+}
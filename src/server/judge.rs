@@ -1,20 +1,35 @@
+use super::callback;
+use super::gzip::GzipJson;
 use super::jwt;
-use crate::judger::{Input, JudgeSpec, Judger, Output};
+use super::limit::SubmissionPermits;
+use super::metrics::metrics;
+use super::shutdown;
+use super::trace::{self, RequestId};
+use crate::config::config;
+use crate::judger::{cases::CasesJudgeSpec, Input, JudgeSpec, Judger, Output};
+use crate::permit::acquire_run_permit;
 use crate::run;
 use base64::engine::general_purpose;
 use base64::Engine;
-use reqwest::Client;
+use rocket::form::{Errors, Form};
+use rocket::http::ContentType;
+use rocket::response::stream::TextStream;
 use rocket::serde::{
-    json::{Error, Json},
+    json::{self, Error, Json},
     Deserialize, Serialize,
 };
-use rocket::tokio::task;
+use rocket::tokio::sync::SemaphorePermit;
+use rocket::tokio::task::JoinHandle;
 use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::Instrument;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct JudgeSubmission {
-    /// The base64-encoded WebAssembly binary
+    /// The base64-encoded WebAssembly binary, left out of multipart uploads
+    #[serde(default)]
     wasm: String,
     /// Judge specifications
     specs: Vec<JudgeSpec>,
@@ -22,7 +37,7 @@ pub struct JudgeSubmission {
     callback: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 #[serde(tag = "type", content = "reason")]
 pub enum JudgeException {
@@ -32,35 +47,141 @@ pub enum JudgeException {
     Output(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct JudgeResult {
     success: bool,
     cost: Option<u64>,
     memory: Option<u32>,
+    /// The memory usage of the program, in bytes
+    memory_bytes: Option<u64>,
+    /// The wall-clock time the program ran for, in milliseconds, excluding compiling it
+    time_ms: Option<u64>,
     message: Option<String>,
     exception: Option<JudgeException>,
+    /// The results of each case of a multi-case spec
+    cases: Option<Vec<JudgeResult>>,
+    /// The points earned by the spec, if it declares any
+    score: Option<f64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub struct SubtaskResult {
+    name: String,
+    success: bool,
+    score: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
 pub struct JudgeResults {
     results: Vec<JudgeResult>,
     error: Option<String>,
+    /// The total points earned, if any spec declares points
+    score: Option<f64>,
+    /// The verdicts of the subtasks, in order of first appearance
+    subtasks: Option<Vec<SubtaskResult>>,
+}
+
+impl JudgeResult {
+    /// The result of a spec that failed before its program ran to completion.
+    fn failure(exception: JudgeException) -> Self {
+        JudgeResult {
+            success: false,
+            cost: None,
+            memory: None,
+            memory_bytes: None,
+            time_ms: None,
+            message: None,
+            exception: Some(exception),
+            cases: None,
+            score: None,
+        }
+    }
+
+    /// The result of a spec whose judging panicked, a bug of the judge rather than of the program.
+    fn panicked() -> Self {
+        let e = run::RunError::internal_panic();
+        JudgeResult {
+            message: Some(e.to_string()),
+            ..JudgeResult::failure(JudgeException::Execution(e.code().to_string()))
+        }
+    }
+
+    /// The result of a program that ran to completion, with its usage, passing unless it has
+    /// an exception.
+    fn from_run(result: &run::RunResult, exception: Option<JudgeException>) -> Self {
+        JudgeResult {
+            success: exception.is_none(),
+            cost: Some(result.cost),
+            memory: Some(result.memory),
+            memory_bytes: Some(result.memory_bytes),
+            time_ms: Some(result.exec_time.as_millis() as u64),
+            message: None,
+            exception,
+            cases: None,
+            score: None,
+        }
+    }
+
+    /// The result of a multi-case spec, passing if all of its cases passed, with their total
+    /// cost and time and their peak memory.
+    fn from_cases(cases: Vec<JudgeResult>) -> Self {
+        JudgeResult {
+            success: cases.iter().all(|case| case.success),
+            cost: Some(cases.iter().filter_map(|case| case.cost).sum()),
+            memory: cases.iter().filter_map(|case| case.memory).max(),
+            memory_bytes: cases.iter().filter_map(|case| case.memory_bytes).max(),
+            time_ms: Some(cases.iter().filter_map(|case| case.time_ms).sum()),
+            message: None,
+            exception: None,
+            cases: Some(cases),
+            score: None,
+        }
+    }
+}
+
+impl JudgeResults {
+    /// Whether the submission was judged and passed every spec.
+    pub fn success(&self) -> bool {
+        self.error.is_none() && self.results.iter().all(|result| result.success)
+    }
 }
 
 #[post("/judge", format = "json", data = "<submission>")]
 pub async fn judge(
-    _token: jwt::Token,
-    submission: Result<Json<JudgeSubmission>, Error<'_>>,
+    _token: jwt::JudgeToken,
+    request_id: &RequestId,
+    submission: Result<GzipJson<JudgeSubmission>, Error<'_>>,
+) -> Json<JudgeResults> {
+    judge_request(submission)
+        .instrument(request_id.span())
+        .await
+}
+
+async fn judge_request(
+    submission: Result<GzipJson<JudgeSubmission>, Error<'_>>,
 ) -> Json<JudgeResults> {
     info!("Received judge request");
+    match decode_submission(submission) {
+        Ok((submission, wasm)) => judge_submission(submission, wasm).await,
+        Err(results) => Json(results),
+    }
+}
+
+/// Parse a JSON judge submission and decode its module, or describe why it is invalid.
+fn decode_submission(
+    submission: Result<GzipJson<JudgeSubmission>, Error<'_>>,
+) -> Result<(JudgeSubmission, Box<[u8]>), JudgeResults> {
     let submission = match submission {
         Ok(submission) => submission.into_inner(),
         Err(e) => {
             info!("Bad judge request: {}", e);
-            return Json(JudgeResults {
+            return Err(JudgeResults {
                 results: vec![],
+                score: None,
+                subtasks: None,
                 error: Some(format!("Invalid submission. Error parsing JSON: {}", e)),
             });
         }
@@ -70,34 +191,149 @@ pub async fn judge(
         Ok(wasm) => wasm.into_boxed_slice(),
         Err(_) => {
             info!("Bad judge request: invalid base64 encoding");
-            return Json(JudgeResults {
+            return Err(JudgeResults {
                 results: vec![],
+                score: None,
+                subtasks: None,
                 error: Some("Invalid submission. Error decoding base64.".to_string()),
             });
         }
     };
 
-    if let Some(callback) = submission.callback {
-        task::spawn(async move {
-            let result = run_specs(wasm, submission.specs).await;
-            let client = Client::new();
-            match client.post(&callback).json(&result).send().await {
-                Ok(res) => {
-                    println!(
-                        "Callback sent successfully. {} ({})",
-                        res.status(),
-                        &callback
-                    );
+    Ok((submission, wasm))
+}
+
+/// Judge a JSON submission like `/judge`, but stream the result of each spec as a line of JSON
+/// (NDJSON) as soon as it is judged, so clients can show the progress of long submissions.
+/// The lines have the shape of the items of `results`, scored with the points of their spec, and
+/// come in the order of the specs, so a spec finishing early is sent once the specs before it are
+/// judged. An invalid submission is answered with a single line with its `error`. The callback of
+/// the submission is ignored.
+#[post("/judge/stream", format = "json", data = "<submission>")]
+pub async fn stream(
+    _token: jwt::JudgeToken,
+    request_id: &RequestId,
+    submission: Result<GzipJson<JudgeSubmission>, Error<'_>>,
+) -> (ContentType, TextStream![String]) {
+    let span = request_id.span();
+    let tasks = span.in_scope(|| {
+        info!("Received judge stream request");
+        decode_submission(submission).and_then(|(submission, wasm)| {
+            check_local_files(&submission.specs).map_err(|e| JudgeResults {
+                results: vec![],
+                score: None,
+                subtasks: None,
+                error: Some(e),
+            })?;
+            let points: Vec<_> = submission
+                .specs
+                .iter()
+                .map(|spec| spec.scoring().1)
+                .collect();
+            Ok((spawn_specs(wasm, submission.specs), points))
+        })
+    });
+
+    let ndjson = ContentType::new("application", "x-ndjson");
+    (
+        ndjson,
+        TextStream! {
+            match tasks {
+                Ok((tasks, points)) => {
+                    for (task, points) in tasks.into_iter().zip(points) {
+                        let mut result = task.await.unwrap_or_else(|_| JudgeResult::panicked());
+                        score_result(&mut result, points);
+                        yield format!("{}\n", json::to_string(&result).unwrap());
+                    }
                 }
-                Err(e) => {
-                    println!("Error sending callback. {} ({})", e, &callback);
+                Err(results) => {
+                    yield format!("{}\n", json::to_string(&results).unwrap());
                 }
             }
+        },
+    )
+}
+
+/// A judge submission uploaded as `multipart/form-data`, with the raw module in its own part.
+#[derive(FromForm)]
+pub struct JudgeUpload<'r> {
+    /// The raw WebAssembly binary
+    wasm: &'r [u8],
+    /// The judge submission as JSON, without the `wasm` field
+    submission: &'r str,
+}
+
+/// Judge a submission uploaded as `multipart/form-data`, like `/judge`.
+#[post("/judge", format = "multipart/form-data", data = "<upload>")]
+pub async fn judge_upload(
+    _token: jwt::JudgeToken,
+    request_id: &RequestId,
+    upload: Result<Form<JudgeUpload<'_>>, Errors<'_>>,
+) -> Json<JudgeResults> {
+    judge_upload_request(upload)
+        .instrument(request_id.span())
+        .await
+}
+
+async fn judge_upload_request(
+    upload: Result<Form<JudgeUpload<'_>>, Errors<'_>>,
+) -> Json<JudgeResults> {
+    info!("Received judge upload");
+    let upload = match upload {
+        Ok(upload) => upload.into_inner(),
+        Err(e) => {
+            info!("Bad judge upload: {}", e);
+            return Json(JudgeResults {
+                results: vec![],
+                score: None,
+                subtasks: None,
+                error: Some(format!("Invalid submission. Error parsing form: {}", e)),
+            });
+        }
+    };
+
+    let submission = match json::from_str::<JudgeSubmission>(upload.submission) {
+        Ok(submission) => submission,
+        Err(e) => {
+            info!("Bad judge upload: {}", e);
+            return Json(JudgeResults {
+                results: vec![],
+                score: None,
+                subtasks: None,
+                error: Some(format!("Invalid submission. Error parsing JSON: {}", e)),
+            });
+        }
+    };
+
+    let wasm = upload.wasm.to_vec().into_boxed_slice();
+    judge_submission(submission, wasm).await
+}
+
+/// Judge a submission with its decoded module, or in the background if it has a callback.
+async fn judge_submission(submission: JudgeSubmission, wasm: Box<[u8]>) -> Json<JudgeResults> {
+    if let Err(e) = check_local_files(&submission.specs) {
+        info!("Bad judge request: {}", e);
+        return Json(JudgeResults {
+            results: vec![],
+            score: None,
+            subtasks: None,
+            error: Some(e),
+        });
+    }
+
+    if let Some(callback) = submission.callback {
+        let background = shutdown::track();
+        trace::spawn(async move {
+            let _background = background;
+            let result = run_specs(wasm, submission.specs).await;
+            callback::send(&callback, &result).await;
         });
 
         Json(JudgeResults {
             results: vec![],
             error: None,
+            score: None,
+            subtasks: None,
         })
     } else {
         let result = run_specs(wasm, submission.specs).await;
@@ -105,119 +341,446 @@ pub async fn judge(
     }
 }
 
+/// Start judging each spec in its own task, in the current span, with no more than
+/// `MAX_PARALLEL_SPECS` of them judged at the same time.
+fn spawn_specs(wasm: Box<[u8]>, specs: Vec<JudgeSpec>) -> Vec<JoinHandle<JudgeResult>> {
+    // Share the module between the specs instead of copying it for each of them
+    let wasm: Arc<[u8]> = wasm.into();
+    let permits = SubmissionPermits::new();
+
+    specs
+        .into_iter()
+        .map(|spec| {
+            let wasm = wasm.clone();
+            let permits = permits.clone();
+            trace::spawn(async move {
+                let _permit = permits.acquire().await;
+                judge_spec(wasm, spec).await
+            })
+        })
+        .collect()
+}
+
+/// Reject the specs reading files from the local disk, which are only allowed in the CLI.
+pub(super) fn check_local_files(specs: &[JudgeSpec]) -> Result<(), String> {
+    match specs.iter().position(|spec| spec.reads_local_files()) {
+        Some(index) => Err(format!(
+            "Invalid submission. Spec {} has an input_file, which is only supported by the CLI",
+            index
+        )),
+        None => Ok(()),
+    }
+}
+
 pub async fn run_specs(wasm: Box<[u8]>, specs: Vec<JudgeSpec>) -> JudgeResults {
-    let mut tasks = Vec::new();
-
-    for spec in specs {
-        let wasm = wasm.clone();
-        let task = task::spawn(async move {
-            let check = spec.check_spec().await;
-            if let Err(e) = check {
-                return (Err(e), Err("".to_string()), None);
-            }
+    let scoring: Vec<_> = specs.iter().map(|spec| spec.scoring()).collect();
+    let tasks = spawn_specs(wasm, specs);
+
+    let mut results = Vec::new();
+
+    for task in tasks {
+        results.push(task.await.unwrap_or_else(|_| JudgeResult::panicked()));
+    }
+
+    let (score, subtasks) = score_results(&mut results, scoring);
+
+    JudgeResults {
+        results,
+        error: None,
+        score,
+        subtasks,
+    }
+}
+
+/// Score a result with the declared points of its spec, all of them if it passes.
+fn score_result(result: &mut JudgeResult, points: Option<f64>) {
+    if let Some(points) = points {
+        result.score = Some(if result.success { points } else { 0.0 });
+    }
+}
 
-            let input = spec.make_input().await;
-            if let Err(e) = input {
-                return (Ok(spec), Err(e), None);
+/// Score the results with the declared subtasks and points of their specs.
+/// A spec earns its points if it passes, and a subtask earns the points of all its specs only if
+/// all of them pass. Returns the total score and the subtask verdicts, if any are declared.
+fn score_results(
+    results: &mut [JudgeResult],
+    scoring: Vec<(Option<String>, Option<f64>)>,
+) -> (Option<f64>, Option<Vec<SubtaskResult>>) {
+    let mut subtasks: Vec<SubtaskResult> = Vec::new();
+    let mut total = None;
+
+    for (result, (subtask, points)) in results.iter_mut().zip(scoring) {
+        score_result(result, points);
+
+        match subtask {
+            Some(name) => {
+                let index = match subtasks.iter().position(|s| s.name == name) {
+                    Some(index) => index,
+                    None => {
+                        subtasks.push(SubtaskResult {
+                            name,
+                            success: true,
+                            score: 0.0,
+                        });
+                        subtasks.len() - 1
+                    }
+                };
+                let subtask = &mut subtasks[index];
+                subtask.success &= result.success;
+                subtask.score += points.unwrap_or(0.0);
+            }
+            None => {
+                if let Some(score) = result.score {
+                    total = Some(total.unwrap_or(0.0) + score);
+                }
             }
-            let input = input.unwrap();
-            let stdin = input.stdin.clone();
+        }
+    }
 
-            let (cost_limit, memory_limit) = spec.limits();
+    for subtask in subtasks.iter_mut() {
+        if !subtask.success {
+            subtask.score = 0.0;
+        }
+        total = Some(total.unwrap_or(0.0) + subtask.score);
+    }
 
-            let task = task::spawn_blocking(move || {
-                info!("Running judge for spec: {:?}", spec);
-                let result = run::run(wasm, cost_limit, memory_limit, stdin);
-                info!("Judge finished for spec: {:?}", spec);
-                (Ok(spec), Ok(input), Some(result))
-            });
+    if subtasks.is_empty() {
+        (total, None)
+    } else {
+        (total, Some(subtasks))
+    }
+}
 
-            task.await.unwrap()
-        });
-        tasks.push(task);
+/// Judge a single spec, compiling the module with the limits of the spec.
+async fn judge_spec(wasm: Arc<[u8]>, spec: JudgeSpec) -> JudgeResult {
+    let check = spec.check_spec().await;
+    if let Err(e) = check {
+        return JudgeResult::failure(JudgeException::Spec(e));
+    }
+
+    if let JudgeSpec::Cases(cases_spec) = spec {
+        return judge_cases(wasm, cases_spec).await;
+    }
+
+    let (cost_limit, memory_limit) = spec.limits();
+    let time_limit = spec.time_limit();
+    let interaction = match &spec {
+        JudgeSpec::Interactive(interactive_spec) => Some(interactive_spec.interaction()),
+        _ => None,
+    };
+    judge_case(spec, move |stdin, permit| {
+        let compiled = run::compile(&wasm, cost_limit, memory_limit, config().compile_options())?;
+        if let Some(interaction) = interaction {
+            return interaction.run(Arc::new(compiled), &stdin, permit);
+        }
+        match time_limit {
+            Some(time_limit) => {
+                run::run_module_timeout(Arc::new(compiled), stdin, time_limit, None, permit)
+            }
+            None => run::run_module(&compiled, stdin),
+        }
+    })
+    .await
+}
+
+/// Judge the cases of a multi-case spec against a module compiled only once.
+/// The inputs of the cases are made first, then `run::run_batch` runs the module with each of
+/// them in turn under a single run permit, each run starting from a clean state, and the outputs
+/// are judged like in `judge_case`.
+async fn judge_cases(wasm: Arc<[u8]>, spec: CasesJudgeSpec) -> JudgeResult {
+    let (cost_limit, memory_limit) = spec.limits();
+    let time_limit = spec.time_limit();
+
+    let mut cases = Vec::new();
+    for case in spec.cases {
+        let input = case.make_input().await;
+        cases.push((case, input));
     }
+    let stdins: Vec<String> = cases
+        .iter()
+        .filter_map(|(_, input)| input.as_ref().ok())
+        .map(|input| input.stdin.clone())
+        .collect();
+    let count = stdins.len();
 
+    let permit = acquire_run_permit().await;
+    let started = Instant::now();
+    let runs = trace::spawn_blocking(move || {
+        let options = config().compile_options();
+        run::run_batch(
+            &wasm,
+            cost_limit,
+            memory_limit,
+            options,
+            stdins,
+            time_limit,
+            permit,
+        )
+    })
+    .await
+    .unwrap_or_else(|_| {
+        (0..count)
+            .map(|_| Err(run::RunError::internal_panic()))
+            .collect()
+    });
+    let elapsed = started.elapsed();
+
+    let mut runs = runs.into_iter();
     let mut results = Vec::new();
+    for (case, input) in cases {
+        let result = match input {
+            Ok(input) => {
+                let outcome = runs.next().expect("Every input is run");
+                // The runs of the batch are timed together, so each case records its own time
+                let elapsed = outcome.as_ref().map_or(elapsed, |result| result.exec_time);
+                judge_run(&case, &input, outcome, elapsed).await
+            }
+            Err(e) => JudgeResult::failure(JudgeException::Input(e)),
+        };
+        results.push(result);
+    }
 
-    for task in tasks {
-        let (spec, input, result) = task.await.unwrap();
-        if let Err(e) = spec {
-            results.push(JudgeResult {
-                success: false,
-                cost: None,
-                memory: None,
-                message: None,
-                exception: Some(JudgeException::Spec(e)),
-            });
-            continue;
+    JudgeResult::from_cases(results)
+}
+
+/// Make the input of a checked spec, run it with the given runner, and judge the output.
+/// The runner is given the run permit, to hold until the module stops running.
+async fn judge_case<F>(spec: JudgeSpec, run: F) -> JudgeResult
+where
+    F: FnOnce(String, SemaphorePermit<'static>) -> Result<run::RunResult, run::RunError>
+        + Send
+        + 'static,
+{
+    let input = match spec.make_input().await {
+        Ok(input) => input,
+        Err(e) => return JudgeResult::failure(JudgeException::Input(e)),
+    };
+    let stdin = input.stdin.clone();
+
+    let permit = acquire_run_permit().await;
+    let started = Instant::now();
+    let task = trace::spawn_blocking(move || {
+        info!("Running judge for spec: {:?}", spec);
+        let result = run(stdin, permit);
+        info!("Judge finished for spec: {:?}", spec);
+        (spec, result)
+    });
+    let joined = task.await;
+    let elapsed = started.elapsed();
+    let (spec, result) = match joined {
+        Ok(joined) => joined,
+        Err(_) => {
+            metrics().record("judge", "RE", elapsed);
+            return JudgeResult::panicked();
         }
-        let spec = spec.unwrap();
+    };
 
-        if let Err(e) = input {
-            results.push(JudgeResult {
-                success: false,
-                cost: None,
-                memory: None,
-                message: None,
-                exception: Some(JudgeException::Input(e)),
-            });
-            continue;
-        }
-        let input = input.unwrap();
-
-        let result = result.unwrap();
-
-        match result {
-            Ok(result) => {
-                let success = spec
-                    .judge_output(
-                        &Input { stdin: input.stdin },
-                        &Output {
-                            stdout: String::from_utf8(result.stdout).unwrap(),
-                            stderr: String::from_utf8(result.stderr).unwrap(),
-                        },
-                    )
-                    .await;
-                if let Err(e) = success {
-                    results.push(JudgeResult {
-                        success: false,
-                        cost: Some(result.cost),
-                        memory: Some(result.memory),
-                        message: None,
-                        exception: Some(JudgeException::Output(e)),
-                    });
-                    continue;
-                }
+    judge_run(&spec, &input, result, elapsed).await
+}
 
-                results.push(JudgeResult {
-                    success: true,
-                    cost: Some(result.cost),
-                    memory: Some(result.memory),
-                    message: None,
-                    exception: None,
-                });
+/// Judge the result of running a spec with its input, which took `elapsed` to run.
+async fn judge_run(
+    spec: &JudgeSpec,
+    input: &Input,
+    result: Result<run::RunResult, run::RunError>,
+    elapsed: Duration,
+) -> JudgeResult {
+    match result {
+        Ok(result) if result.exit_code != 0 => {
+            metrics().record("judge", "RE", elapsed);
+            let exception = JudgeException::Execution("RE".to_string());
+            JudgeResult {
+                message: Some(format!("Exited with code {}", result.exit_code)),
+                ..JudgeResult::from_run(&result, Some(exception))
             }
-            Err(e) => {
-                let exception = match e {
-                    run::RunError::SpendingLimitExceeded(_) => "SLE",
-                    run::RunError::MemoryLimitExceeded(_) => "MLE",
-                    run::RunError::RuntimeError(_) => "RE",
-                    run::RunError::CompileError(_) => "CE",
-                    run::RunError::IOError(_) => "IOE",
-                };
-                results.push(JudgeResult {
-                    success: false,
-                    cost: None,
-                    memory: None,
-                    message: None,
-                    exception: Some(JudgeException::Execution(exception.to_string())),
-                });
+        }
+        Ok(mut result) => {
+            // Most judges compare text, so binary output can never be accepted by them
+            if !spec.binary_output() {
+                if let Err(e) = std::str::from_utf8(&result.stdout) {
+                    metrics().record("judge", "RE", elapsed);
+                    let exception = JudgeException::Execution("RE".to_string());
+                    return JudgeResult {
+                        message: Some(format!(
+                            "Wrote invalid UTF-8 to stdout at byte {}",
+                            e.valid_up_to()
+                        )),
+                        ..JudgeResult::from_run(&result, Some(exception))
+                    };
+                }
+            }
+
+            let success = spec
+                .judge_output(
+                    input,
+                    &Output {
+                        stdout: std::mem::take(&mut result.stdout),
+                        stderr: String::from_utf8_lossy(&result.stderr).into_owned(),
+                        verdict: result.verdict.take(),
+                    },
+                )
+                .await;
+            let verdict = if success.is_ok() { "AC" } else { "WA" };
+            metrics().record("judge", verdict, elapsed);
+            JudgeResult::from_run(&result, success.err().map(JudgeException::Output))
+        }
+        Err(e) => {
+            metrics().record("judge", e.code(), elapsed);
+            // A program stopped at a limit used at least the limit
+            let (cost, memory, time_ms) = match e {
+                run::RunError::SpendingLimitExceeded(cost) => (Some(cost), None, None),
+                run::RunError::MemoryLimitExceeded(memory) => (None, Some(memory), None),
+                run::RunError::TimeLimitExceeded(time_ms) => (None, None, Some(time_ms)),
+                _ => (None, None, None),
+            };
+            JudgeResult {
+                cost,
+                memory,
+                time_ms,
+                message: Some(e.to_string()),
+                ..JudgeResult::failure(JudgeException::Execution(e.code().to_string()))
             }
         }
     }
+}
 
-    JudgeResults {
-        results,
-        error: None,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::judger::hash_digest;
+    use crate::server::core::rocket;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json::{json, Value};
+
+    /// A module writing the bytes of the WAT string to stdout.
+    fn printing(data: &str, len: usize) -> String {
+        format!(
+            r#"
+            (module
+                (import "wasi_snapshot_preview1" "fd_write"
+                    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 16) "{}")
+                (func (export "_start")
+                    (i32.store (i32.const 0) (i32.const 16))
+                    (i32.store (i32.const 4) (i32.const {}))
+                    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+            "#,
+            data, len
+        )
+    }
+
+    /// Post the WAT module with the specs to a judge endpoint, returning the body.
+    async fn post(path: &str, wat: &str, specs: Value) -> String {
+        let client = Client::tracked(rocket()).await.unwrap();
+        let submission = json!({
+            "wasm": general_purpose::STANDARD.encode(wat::parse_str(wat).unwrap()),
+            "specs": specs,
+        });
+
+        let response = client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", jwt::test_token()),
+            ))
+            .body(submission.to_string())
+            .dispatch()
+            .await;
+        response.into_string().await.unwrap()
+    }
+
+    /// Judge the WAT module against the specs through `/judge`.
+    async fn judge(wat: &str, specs: Value) -> JudgeResults {
+        json::from_str(&post("/judge", wat, specs).await).unwrap()
+    }
+
+    #[rocket::async_test]
+    async fn io_fast_spec_passes() {
+        let specs = json!([{
+            "judger": "IOFast",
+            "input": "",
+            "output_hash": hash_digest(&None, b"hello"),
+            "cost": 1_000_000,
+            "memory": 1,
+        }]);
+        let results = judge(&printing("hello", 5), specs).await;
+
+        assert!(results.error.is_none(), "{:?}", results);
+        assert_eq!(results.results.len(), 1);
+        assert!(results.results[0].success, "{:?}", results);
+    }
+
+    #[rocket::async_test]
+    async fn binary_stdout_is_runtime_error() {
+        let specs = json!([{
+            "judger": "IOFast",
+            "input": "",
+            "expected_output": "",
+            "cost": 1_000_000,
+            "memory": 1,
+        }]);
+        let results = judge(&printing("\\ff", 1), specs).await;
+
+        let result = &results.results[0];
+        assert!(!result.success);
+        assert!(matches!(&result.exception, Some(JudgeException::Execution(code)) if code == "RE"));
+        assert_eq!(
+            result.message.as_deref(),
+            Some("Wrote invalid UTF-8 to stdout at byte 0")
+        );
+    }
+
+    #[rocket::async_test]
+    async fn stream_scores_like_judge() {
+        let specs = json!([
+            {
+                "judger": "IOFast",
+                "input": "",
+                "expected_output": "hello",
+                "points": 3.0,
+                "cost": 1_000_000,
+                "memory": 1,
+            },
+            {
+                "judger": "IOFast",
+                "input": "",
+                "expected_output": "bye",
+                "points": 2.0,
+                "cost": 1_000_000,
+                "memory": 1,
+            },
+        ]);
+        let wat = printing("hello", 5);
+        let results = judge(&wat, specs.clone()).await;
+        let lines = post("/judge/stream", &wat, specs).await;
+        let streamed: Vec<JudgeResult> = lines
+            .lines()
+            .map(|line| json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(streamed.len(), results.results.len());
+        for (streamed, result) in streamed.iter().zip(&results.results) {
+            assert_eq!(streamed.success, result.success);
+            assert_eq!(streamed.score, result.score);
+        }
+        assert_eq!(streamed[0].score, Some(3.0));
+        assert_eq!(streamed[1].score, Some(0.0));
+    }
+
+    #[rocket::async_test]
+    async fn binary_stdout_matches_output_hash() {
+        let specs = json!([{
+            "judger": "IOFast",
+            "input": "",
+            "output_hash": hash_digest(&None, b"\xff"),
+            "cost": 1_000_000,
+            "memory": 1,
+        }]);
+        let results = judge(&printing("\\ff", 1), specs).await;
+
+        assert!(results.results[0].success, "{:?}", results);
     }
 }
@@ -1,30 +1,131 @@
 use crate::config::*;
 use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use rocket::fairing::AdHoc;
 use rocket::request::{self, FromRequest, Request};
-use rocket::serde::json::Json;
+use rocket::serde::json::{Json, Value};
 use rocket::serde::{Deserialize, Serialize};
+use std::fs;
+use std::sync::OnceLock;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "rocket::serde")]
-struct Claims {
-    exp: usize,
+pub struct Claims {
+    pub exp: usize,
+    /// The subject of the token, such as the user or service it was issued to
+    pub sub: Option<String>,
+    /// The audience of the token, a string or an array of strings
+    pub aud: Option<Value>,
+    /// The actions the token grants, such as `run` and `judge`, all of them if not set
+    pub scopes: Option<Vec<String>>,
 }
 
-pub fn is_valid_token(token: &str) -> bool {
-    let secret = app_secret();
+impl Claims {
+    /// Check if the token grants the given scope.
+    pub fn has_scope(&self, scope: &str) -> bool {
+        match &self.scopes {
+            Some(scopes) => scopes.iter().any(|s| s == scope),
+            None => true,
+        }
+    }
+}
+
+/// The algorithm and key verifying the tokens, or why they could not be loaded.
+static DECODING_KEY: OnceLock<Result<(Algorithm, DecodingKey), String>> = OnceLock::new();
+
+/// Get the algorithm and key verifying the tokens, loading them on first use.
+fn decoding_key() -> &'static Result<(Algorithm, DecodingKey), String> {
+    DECODING_KEY.get_or_init(load_decoding_key)
+}
+
+/// Load the algorithm and key verifying the tokens, as configured by `JWT_ALGORITHM`.
+/// HS256 tokens are verified with the application secret, RS256 and ES256 tokens with the
+/// PEM public key in `JWT_PUBLIC_KEY`, so the server never holds their signing key.
+fn load_decoding_key() -> Result<(Algorithm, DecodingKey), String> {
+    let algorithm = jwt_algorithm();
+    let pem = || -> Result<Vec<u8>, String> {
+        let key = jwt_public_key()
+            .ok_or_else(|| format!("JWT_PUBLIC_KEY is required for {}", algorithm))?;
+        if key.trim_start().starts_with("-----BEGIN") {
+            Ok(key.into_bytes())
+        } else {
+            fs::read(&key).map_err(|e| format!("Error reading JWT_PUBLIC_KEY {}: {}", key, e))
+        }
+    };
+
+    match algorithm.as_str() {
+        "HS256" => Ok((
+            Algorithm::HS256,
+            DecodingKey::from_secret(config().app_secret.as_bytes()),
+        )),
+        "RS256" => DecodingKey::from_rsa_pem(&pem()?)
+            .map(|key| (Algorithm::RS256, key))
+            .map_err(|e| format!("Invalid JWT_PUBLIC_KEY: {}", e)),
+        "ES256" => DecodingKey::from_ec_pem(&pem()?)
+            .map(|key| (Algorithm::ES256, key))
+            .map_err(|e| format!("Invalid JWT_PUBLIC_KEY: {}", e)),
+        _ => Err(format!(
+            "Unknown JWT_ALGORITHM {}, supported algorithms are HS256, RS256 and ES256",
+            algorithm
+        )),
+    }
+}
 
-    let validation = Validation::new(Algorithm::HS256);
+/// A fairing loading the key verifying the tokens at launch, so a missing or invalid
+/// `JWT_PUBLIC_KEY` stops the server from starting instead of failing every request.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("JWT Decoding Key", |rocket| async {
+        match decoding_key() {
+            Ok(_) => Ok(rocket),
+            Err(e) => {
+                error!("{}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
 
-    let token = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &validation,
-    );
+/// Decode the claims of the given token, if it is valid.
+pub fn decode_token(token: &str) -> Option<Claims> {
+    let (algorithm, key) = match decoding_key() {
+        Ok(decoding_key) => decoding_key,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
 
-    token.is_ok()
+    let mut validation = Validation::new(*algorithm);
+    if let Some(audience) = jwt_audience() {
+        validation.set_audience(&[audience]);
+    }
+    if let Some(issuer) = jwt_issuer() {
+        validation.set_issuer(&[issuer]);
+    }
+
+    let token = decode::<Claims>(token, key, &validation);
+
+    token.ok().map(|token| token.claims)
 }
 
-pub struct Token(String);
+/// Sign a token granting every scope with the application secret, for the tests of the routes.
+#[cfg(test)]
+pub fn test_token() -> String {
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    let claims = Claims {
+        exp: usize::MAX,
+        sub: None,
+        aud: None,
+        scopes: None,
+    };
+    let key = EncodingKey::from_secret(config().app_secret.as_bytes());
+    encode(&Header::default(), &claims, &key).unwrap()
+}
+
+/// A valid token, with its decoded claims.
+pub struct Token {
+    pub claims: Claims,
+}
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for Token {
@@ -40,13 +141,44 @@ impl<'r> FromRequest<'r> for Token {
         let key = key.to_string();
         let key = key.replace("Bearer ", "");
 
-        let valid = is_valid_token(&key);
+        match decode_token(&key) {
+            Some(claims) => request::Outcome::Success(Token { claims }),
+            None => request::Outcome::Failure((rocket::http::Status::Unauthorized, ())),
+        }
+    }
+}
 
-        if valid {
-            request::Outcome::Success(Token(key))
-        } else {
-            request::Outcome::Failure((rocket::http::Status::Unauthorized, ()))
+/// Get the token of the request, failing with 403 if it does not grant the scope.
+async fn scoped_token(request: &Request<'_>, scope: &str) -> request::Outcome<Token, ()> {
+    match Token::from_request(request).await {
+        request::Outcome::Success(token) if !token.claims.has_scope(scope) => {
+            request::Outcome::Failure((rocket::http::Status::Forbidden, ()))
         }
+        outcome => outcome,
+    }
+}
+
+/// A valid token granting the `run` scope.
+pub struct RunToken(pub Token);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for RunToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        scoped_token(request, "run").await.map(RunToken)
+    }
+}
+
+/// A valid token granting the `judge` scope.
+pub struct JudgeToken(pub Token);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for JudgeToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        scoped_token(request, "judge").await.map(JudgeToken)
     }
 }
 
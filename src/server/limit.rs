@@ -0,0 +1,30 @@
+use crate::config::*;
+use rocket::tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use std::sync::Arc;
+
+/// The permits of a single submission to judge its specs, so no more than `MAX_PARALLEL_SPECS` of
+/// them are judged at the same time, and a submission with many specs does not queue all of them
+/// for the run permits ahead of the other submissions.
+#[derive(Clone)]
+pub struct SubmissionPermits(Arc<Semaphore>);
+
+impl SubmissionPermits {
+    pub fn new() -> Self {
+        SubmissionPermits(Arc::new(Semaphore::new(max_parallel_specs())))
+    }
+
+    /// Wait for a permit to judge a spec of the submission.
+    pub async fn acquire(&self) -> OwnedSemaphorePermit {
+        self.0
+            .clone()
+            .acquire_owned()
+            .await
+            .expect("The submission semaphore is never closed")
+    }
+}
+
+impl Default for SubmissionPermits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
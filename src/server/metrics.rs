@@ -0,0 +1,106 @@
+use crate::run::{RunError, RunResult};
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use rocket::http::ContentType;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The Prometheus metrics of the server.
+pub struct Metrics {
+    registry: Registry,
+    /// The number of runs, by endpoint
+    runs: IntCounterVec,
+    /// The number of runs, by endpoint and verdict
+    verdicts: IntCounterVec,
+    /// The wall-clock time of runs, by endpoint
+    run_duration: HistogramVec,
+    /// The number of unfinished jobs
+    queued_jobs: IntGauge,
+}
+
+static METRICS: OnceLock<Metrics> = OnceLock::new();
+
+/// Get the metrics of the server, registering them on first use.
+pub fn metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| {
+        let registry = Registry::new_custom(Some("wark".to_string()), None).unwrap();
+
+        let runs = IntCounterVec::new(Opts::new("runs_total", "The number of runs"), &["endpoint"])
+            .unwrap();
+        let verdicts = IntCounterVec::new(
+            Opts::new("verdicts_total", "The number of runs by verdict"),
+            &["endpoint", "verdict"],
+        )
+        .unwrap();
+        let run_duration = HistogramVec::new(
+            HistogramOpts::new("run_duration_seconds", "The wall-clock time of runs").buckets(
+                vec![
+                    0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+                ],
+            ),
+            &["endpoint"],
+        )
+        .unwrap();
+        let queued_jobs = IntGauge::new("queued_jobs", "The number of unfinished jobs").unwrap();
+
+        registry.register(Box::new(runs.clone())).unwrap();
+        registry.register(Box::new(verdicts.clone())).unwrap();
+        registry.register(Box::new(run_duration.clone())).unwrap();
+        registry.register(Box::new(queued_jobs.clone())).unwrap();
+
+        Metrics {
+            registry,
+            runs,
+            verdicts,
+            run_duration,
+            queued_jobs,
+        }
+    })
+}
+
+impl Metrics {
+    /// Record a run of an endpoint, with its verdict and wall-clock time.
+    pub fn record_run(
+        &self,
+        endpoint: &str,
+        result: &Result<RunResult, RunError>,
+        elapsed: Duration,
+    ) {
+        let verdict = match result {
+            Ok(result) if result.exit_code != 0 => "RE",
+            Ok(_) => "OK",
+            Err(e) => e.code(),
+        };
+        self.record(endpoint, verdict, elapsed);
+    }
+
+    /// Record a run of an endpoint, with the given verdict and wall-clock time.
+    pub fn record(&self, endpoint: &str, verdict: &str, elapsed: Duration) {
+        self.runs.with_label_values(&[endpoint]).inc();
+        self.verdicts.with_label_values(&[endpoint, verdict]).inc();
+        self.run_duration
+            .with_label_values(&[endpoint])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Set the number of unfinished jobs.
+    pub fn set_queued_jobs(&self, count: usize) {
+        self.queued_jobs.set(count as i64);
+    }
+}
+
+/// Export the metrics in the Prometheus text format.
+#[get("/metrics")]
+pub fn export() -> (ContentType, String) {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder
+        .encode(&metrics().registry.gather(), &mut buffer)
+        .unwrap();
+
+    (
+        ContentType::Plain,
+        String::from_utf8(buffer).unwrap_or_default(),
+    )
+}
@@ -1,6 +1,15 @@
+mod callback;
 mod compress;
 pub mod core;
 mod execute;
+mod gzip;
+mod jobs;
 mod judge;
 mod jwt;
+mod limit;
+mod metrics;
+mod shutdown;
+mod trace;
 mod version;
+
+pub use judge::{run_specs, JudgeResults};
@@ -0,0 +1,49 @@
+use crate::config::*;
+use rocket::fairing::AdHoc;
+use rocket::tokio::time::{sleep, Instant};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The number of background tasks still running, such as jobs and judges with a callback.
+static BACKGROUND_TASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// A background task tracked until it is dropped, so the server can wait for it on shutdown.
+pub struct BackgroundTask(());
+
+/// Track a background task until the returned guard is dropped.
+pub fn track() -> BackgroundTask {
+    BACKGROUND_TASKS.fetch_add(1, Ordering::SeqCst);
+    BackgroundTask(())
+}
+
+impl Drop for BackgroundTask {
+    fn drop(&mut self) {
+        BACKGROUND_TASKS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Wait for the background tasks to finish on shutdown, up to the `DRAIN_TIMEOUT`.
+/// In-flight requests are drained by Rocket itself within the same timeout.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_shutdown("Drain Background Tasks", |_| {
+        Box::pin(async move {
+            let deadline = Instant::now() + Duration::from_secs(drain_timeout());
+
+            loop {
+                let running = BACKGROUND_TASKS.load(Ordering::SeqCst);
+                if running == 0 {
+                    info!("All background tasks finished");
+                    return;
+                }
+
+                if Instant::now() >= deadline {
+                    warn!("Shutting down with {} background tasks running", running);
+                    return;
+                }
+
+                info!("Waiting for {} background tasks to finish", running);
+                sleep(Duration::from_millis(500)).await;
+            }
+        })
+    })
+}
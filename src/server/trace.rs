@@ -0,0 +1,82 @@
+use rocket::fairing::AdHoc;
+use rocket::http::Header;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::tokio::task::{self, JoinHandle};
+use std::backtrace::Backtrace;
+use std::future::Future;
+use std::{fmt, panic};
+use tracing::{Instrument, Span};
+use uuid::Uuid;
+
+/// The header the request ID is echoed back in.
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// A unique ID generated for each request, to correlate the log lines of the same request.
+#[derive(Debug, Clone)]
+pub struct RequestId(String);
+
+impl RequestId {
+    fn new() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+
+    /// The span of the request, carrying its ID into the log lines recorded inside it.
+    pub fn span(&self) -> Span {
+        tracing::info_span!("request", id = %self.0)
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The ID of the request, generated on first use and cached for the rest of the request.
+fn request_id<'r>(req: &'r Request<'_>) -> &'r RequestId {
+    req.local_cache(RequestId::new)
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r RequestId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        Outcome::Success(request_id(req))
+    }
+}
+
+pub fn fairing() -> AdHoc {
+    AdHoc::on_response("Request ID", |req, res| {
+        Box::pin(async move {
+            res.set_header(Header::new(REQUEST_ID_HEADER, request_id(req).to_string()));
+        })
+    })
+}
+
+/// Spawn a task in the current span, so its log lines carry the ID of the request.
+pub fn spawn<F>(future: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    task::spawn(future.in_current_span())
+}
+
+/// Spawn a blocking task in the current span, so its log lines carry the ID of the request.
+pub fn spawn_blocking<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let span = Span::current();
+    task::spawn_blocking(move || span.in_scope(f))
+}
+
+/// Log panics with their backtrace in the span they happen in, so the panic of a run is recorded
+/// with the ID of its request, even though the run itself recovers from it.
+pub fn set_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        tracing::error!("{}\n{}", info, Backtrace::force_capture());
+    }));
+}
@@ -0,0 +1,19 @@
+#include <stdio.h>
+#include <string.h>
+#define KB 1024ULL
+#define MB 1024 * KB
+#define SIZE 16 * MB
+
+char buffer[SIZE];
+
+int main() {
+  memset(buffer, 1, SIZE);
+
+  for (size_t i = 0; i < SIZE; i++) {
+    buffer[i] = 2;
+  }
+
+  printf("%d\n", buffer[SIZE - 1]);
+
+  return 0;
+}